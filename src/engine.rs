@@ -1,33 +1,51 @@
 pub mod camera;
+pub mod controller;
 pub mod game;
+pub mod material;
 pub mod math;
 pub mod mesh;
 pub mod model;
+pub mod profiler;
 pub mod renderer;
 pub mod texture;
 
-use std::{rc::Rc, time::SystemTime};
+use std::{
+  rc::Rc,
+  time::{Instant, SystemTime},
+};
 
-use crate::input::Input;
+use crate::input::{
+  action::{Action, ActionMap},
+  replay::{InputRecorder, InputReplay},
+  Input,
+};
 
 use self::{
+  camera::path::CameraPlayback,
+  controller::set_cursor_grab,
   game::{
     block::{model::BlockModel, registry::BlockRegistry, Block},
-    world::World,
+    clock::WorldClock,
+    editor::BlockEditor,
+    hotbar::Hotbar,
+    scene::Scene,
+    state::GameState,
+    world::{history::EditHistory, raycast::RaycastHit, World},
   },
+  material::{Material, MaterialDesc},
   math::Orientation2,
-  renderer::BloomRenderer,
+  profiler::Profiler,
+  renderer::{hud::Hud, BloomRenderer},
   texture::BloomTexture,
 };
 use anyhow::*;
-use cgmath::{Deg, Vector3};
+use cgmath::{Array, EuclideanSpace, Point3, Vector3};
 use wgpu::{BindGroupLayout, Device, Queue};
 use winit::{
-  event::{Event, VirtualKeyCode, WindowEvent},
+  event::{Event, WindowEvent},
   event_loop::{ControlFlow, EventLoop},
   window::{Window, WindowAttributes, WindowId},
 };
-use winit_input_helper::WinitInputHelper;
 
 pub struct BloomEngine {
   pub renderer: BloomRenderer,
@@ -35,7 +53,8 @@ pub struct BloomEngine {
   pub window: Window,
 
   pub block_registry: BlockRegistry,
-  pub world: World,
+  pub state: GameState,
+  pub profiler: Profiler,
   // textures: HashMap<String, Rc<BloomTexture>>,
 }
 
@@ -45,47 +64,69 @@ impl BloomEngine {
     let renderer = BloomRenderer::new(&window).await;
 
     let (block_registry, world) = Self::init(
-      &renderer.texture_bind_group_layout,
+      &renderer.material_bind_group_layout,
       &renderer.device,
       &renderer.queue,
     )
     .unwrap();
+    let profiler = Profiler::from_env().unwrap();
+    let hotbar = Hotbar::from_registry(&block_registry);
+    let state = GameState::new(
+      world,
+      BlockEditor::new(hotbar, EditHistory::from_env()),
+      CameraPlayback::from_env().unwrap(),
+      &renderer.camera,
+    );
 
     Self {
       renderer,
       event_loop,
       window,
       block_registry,
-      world,
+      state,
+      profiler,
     }
   }
 
   pub fn init(
-    texture_bind_group_layout: &BindGroupLayout,
+    material_bind_group_layout: &BindGroupLayout,
     device: &Device,
     queue: &Queue,
   ) -> Result<(BlockRegistry, World)> {
-    let stone_texture = Rc::new(BloomTexture::from_raw_rbga(
+    let stone_texture = BloomTexture::from_raw_rbga(
       "stone",
       include_bytes!("engine/game/textures/stone.png"),
-      texture_bind_group_layout,
       device,
       queue,
-    )?);
-    let glass_texture = Rc::new(BloomTexture::from_raw_rbga(
+    )?;
+    let glass_texture = BloomTexture::from_raw_rbga(
       "glass",
       include_bytes!("engine/game/textures/glass.png"),
-      texture_bind_group_layout,
       device,
       queue,
-    )?);
-    let oak_log_texture = Rc::new(BloomTexture::from_raw_rbga(
+    )?;
+    let oak_log_texture = BloomTexture::from_raw_rbga(
       "oak_log",
       include_bytes!("engine/game/textures/oak_log.png"),
-      texture_bind_group_layout,
       device,
       queue,
-    )?);
+    )?;
+
+    let material = |label: &str, desc: MaterialDesc, texture: &BloomTexture| {
+      Rc::new(Material::new(
+        label,
+        desc,
+        texture,
+        material_bind_group_layout,
+        device,
+      ))
+    };
+    let stone_material =
+      material("material:stone", MaterialDesc::OPAQUE, &stone_texture);
+    let oak_log_material =
+      material("material:oak_log", MaterialDesc::OPAQUE, &oak_log_texture);
+    let glass_material =
+      material("material:glass", MaterialDesc::TRANSLUCENT, &glass_texture);
 
     let simple_model = Rc::new(BlockModel::model_simple());
     let side_vert_model = Rc::new(BlockModel::model_side_vert());
@@ -93,13 +134,13 @@ impl BloomEngine {
       Rc::new(BlockModel::model_simple_transparent());
 
     let stone_block =
-      Rc::new(Block::new("stone", &simple_model, &stone_texture));
+      Rc::new(Block::new("stone", &simple_model, &stone_material));
     let oak_log_block =
-      Rc::new(Block::new("oak_log", &side_vert_model, &oak_log_texture));
+      Rc::new(Block::new("oak_log", &side_vert_model, &oak_log_material));
     let glass_block = Rc::new(Block::new(
       "glass",
       &simple_transparent_model,
-      &glass_texture,
+      &glass_material,
     ));
 
     let mut block_registry = BlockRegistry::new();
@@ -107,9 +148,7 @@ impl BloomEngine {
     block_registry.register_block(&oak_log_block);
     block_registry.register_block(&glass_block);
 
-    let mut world = World::new();
-    world.set_block((1, 1, 1).into(), Some(&stone_block));
-    world.set_block((1, 0, 1).into(), Some(&oak_log_block));
+    let world = Scene::from_env().build(&block_registry);
 
     Ok((block_registry, world))
   }
@@ -120,19 +159,20 @@ impl BloomEngine {
       event_loop,
       window,
 
-      block_registry,
-      mut world,
+      block_registry: _,
+      mut state,
+      mut profiler,
     } = self;
 
     let mut last_frame_time = SystemTime::now();
-    let mut input = Input::new();
+    let mut input = Input::new(ActionMap::from_env().unwrap());
+    let mut input_recorder = InputRecorder::from_env().unwrap();
+    let mut input_replay = InputReplay::from_env().unwrap();
     event_loop.run(move |event, eloop| {
-      let delta = last_frame_time.elapsed().unwrap().as_secs_f32();
-      last_frame_time = SystemTime::now();
-
-      input.update(&event);
-
-      Self::update(delta, &input, &mut renderer, &mut world, &block_registry);
+      // Replays ignore the real input entirely
+      if input_replay.is_none() {
+        input.update(&event);
+      }
 
       match event {
         //Event::AboutToWait => window.request_redraw(),
@@ -145,94 +185,254 @@ impl BloomEngine {
             renderer.resize(*physical_size);
           }
           WindowEvent::CloseRequested => {
+            if let Some(recorder) = input_recorder.take() {
+              let end = state.session_end(&renderer.camera);
+              if let Err(error) = recorder.finish(end) {
+                eprintln!("Could not finish the input recording: {}", error);
+              }
+            }
             eloop.set_control_flow(ControlFlow::Exit)
           }
           _ => {}
         },
         _ => {}
       }
+
+      // Every event of the frame came in, the input is complete
+      if !matches!(event, Event::AboutToWait) {
+        return;
+      }
+      let mut delta = last_frame_time.elapsed().unwrap().as_secs_f32();
+      last_frame_time = SystemTime::now();
+
+      if let Some(replay) = &mut input_replay {
+        match replay.next_frame() {
+          Some(frame) => {
+            delta = frame.delta;
+            for event in frame.events {
+              input.apply(event);
+            }
+          }
+          None => {
+            let reached = state.session_end(&renderer.camera);
+            match replay.end().map(|end| end.check(reached)) {
+              Some(Err(error)) => {
+                eprintln!("Replay diverged from the recording: {}", error);
+                std::process::exit(1);
+              }
+              Some(_) => println!("Replay matched the recording"),
+              None => println!("Replay finished, the recording has no end"),
+            }
+            eloop.set_control_flow(ControlFlow::Exit);
+            return;
+          }
+        }
+      }
+      if let Some(recorder) = &mut input_recorder {
+        if let Err(error) = recorder.record_frame(delta, input.events()) {
+          eprintln!("Stopped recording the input: {}", error);
+          input_recorder = None;
+        }
+      }
+
+      let update_start = Instant::now();
+      let grabbed = state.cursor_grabbed;
+      let target = state.update(delta, &input, &mut renderer.camera);
+      if state.cursor_grabbed != grabbed {
+        set_cursor_grab(&window, state.cursor_grabbed);
+      }
+      Self::update(&input, target, state.world.clock(), &mut renderer);
+      profiler.record("update", update_start.elapsed().as_secs_f32());
+
+      input.end_frame();
     });
   }
 
   pub fn redraw(&mut self) {
-    let meshes = self.world.meshes(
-      &self.block_registry,
-      &self.renderer.camera,
-      &self.renderer.device,
-    );
+    if self.renderer.settings.chunk_bounds {
+      self
+        .renderer
+        .set_chunk_bounds(self.state.world.chunk_bounds());
+    }
+    let meshing_start = Instant::now();
+    self
+      .state
+      .world
+      .update_meshes(&self.block_registry, &self.renderer.device);
+    self
+      .profiler
+      .record("meshing", meshing_start.elapsed().as_secs_f32());
+
+    let meshes = self.state.world.meshes(&self.renderer.camera);
+    if self.renderer.settings.debug_panel {
+      Self::draw_debug_panel(
+        &mut self.renderer,
+        &self.profiler,
+        self.state.controller.name(),
+        self.state.world.chunk_count(),
+        meshes.len(),
+      );
+    }
+    Self::draw_hotbar(&mut self.renderer, &self.state.editor.hotbar);
+    let render_start = Instant::now();
     self.renderer.render(&meshes).unwrap();
+    self
+      .profiler
+      .record("render", render_start.elapsed().as_secs_f32());
+
+    self
+      .renderer
+      .gpu_pass_times()
+      .iter()
+      .for_each(|(pass, seconds)| {
+        self.profiler.record(&format!("gpu:{}", pass), *seconds)
+      });
+    self.profiler.end_frame();
   }
 
-  fn update(
-    delta: f32,
-    input: &WinitInputHelper,
+  // F3 panel in the top left corner of the screen
+  fn draw_debug_panel(
     renderer: &mut BloomRenderer,
-    world: &mut World,
-    block_registry: &BlockRegistry,
+    profiler: &Profiler,
+    controller: &str,
+    chunk_count: usize,
+    mesh_count: usize,
   ) {
-    let camera_speed = 7.0 * delta;
-    let sensitivity = 120.0 * delta;
-
-    let camera = &mut renderer.camera;
-
-    let mut displacement: Vector3<f32> = (0.0, 0.0, 0.0).into();
-    if input.key_held(VirtualKeyCode::W) {
-      displacement -= camera.forward() * camera_speed;
-    }
-    if input.key_held(VirtualKeyCode::S) {
-      displacement += camera.forward() * camera_speed;
-    }
-
-    if input.key_held(VirtualKeyCode::D) {
-      displacement -= camera.left() * camera_speed;
-    }
-    if input.key_held(VirtualKeyCode::A) {
-      displacement += camera.left() * camera_speed;
-    }
-    if input.key_held(VirtualKeyCode::Space) {
-      displacement += camera.up() * camera_speed;
-    }
-    if input.key_held(VirtualKeyCode::LShift) {
-      displacement -= camera.up() * camera_speed;
-    }
+    let frame_time = renderer.frame_time();
+    let fps = if frame_time > 0.0 {
+      1.0 / frame_time
+    } else {
+      0.0
+    };
+    let position = renderer.camera.position();
+    let orientation = renderer.camera.orientation();
+    let angles = Orientation2::from(orientation);
+    let mut text = format!(
+      "FPS {:.0} ({:.2} ms)\n\
+      XYZ {:.2} {:.2} {:.2}\n\
+      Pitch {:.1} Yaw {:.1} Roll {:.1}\n\
+      Camera {}\n\
+      Chunks {}\n\
+      Meshes {}\n\
+      Projection {:?}\n\
+      Transparency {:?}\n\
+      Antialiasing {:?}\n\
+      View {:?}",
+      fps,
+      frame_time * 1000.0,
+      position.x,
+      position.y,
+      position.z,
+      angles.pitch.0,
+      angles.yaw.0,
+      orientation.roll().0,
+      controller,
+      chunk_count,
+      mesh_count,
+      renderer.camera.projection(),
+      renderer.settings.transparency,
+      renderer.settings.antialiasing,
+      renderer.settings.debug_view,
+    );
+    // Averages over the last frames
+    profiler.timings().for_each(|(name, timing)| {
+      text += &format!("\n{} {:.2} ms", name, timing.average() * 1000.0);
+    });
+    let (scale, margin) = (2.0, 8.0);
+    let (width, height) = Hud::text_size(&text, scale);
+    renderer.hud.rect(
+      0.0,
+      0.0,
+      width + margin * 2.0,
+      height + margin * 2.0,
+      [0.0, 0.0, 0.0, 0.5],
+    );
+    renderer
+      .hud
+      .text(margin, margin, &text, scale, [1.0, 1.0, 1.0, 1.0]);
+  }
 
-    let mut delta_orientation: Orientation2 = (0.0, 0.0).into();
-    if input.key_held(VirtualKeyCode::I) {
-      delta_orientation -= (0.0, sensitivity).into();
-    }
-    if input.key_held(VirtualKeyCode::K) {
-      delta_orientation += (0.0, sensitivity).into();
+  // Blocks at the bottom of the screen, the selected one highlighted
+  fn draw_hotbar(renderer: &mut BloomRenderer, hotbar: &Hotbar) {
+    let (scale, margin, spacing) = (2.0, 6.0, 4.0);
+    let slot_width =
+      |block: &Block| Hud::text_size(block.name(), scale).0 + margin * 2.0;
+    let width = hotbar
+      .blocks()
+      .iter()
+      .map(|block| slot_width(block) + spacing)
+      .sum::<f32>()
+      - spacing;
+    let height = Hud::line_height(scale) + margin * 2.0;
+    let mut x = (renderer.hud.width() as f32 - width) / 2.0;
+    let y = renderer.hud.height() as f32 - height - margin * 2.0;
+    for (slot, block) in hotbar.blocks().iter().enumerate() {
+      let background = if slot == hotbar.selected_slot() {
+        [1.0, 1.0, 1.0, 0.4]
+      } else {
+        [0.0, 0.0, 0.0, 0.5]
+      };
+      renderer
+        .hud
+        .rect(x, y, slot_width(block), height, background);
+      renderer.hud.text(
+        x + margin,
+        y + margin,
+        block.name(),
+        scale,
+        [1.0, 1.0, 1.0, 1.0],
+      );
+      x += slot_width(block) + spacing;
     }
+  }
 
-    if input.key_held(VirtualKeyCode::L) {
-      delta_orientation += (sensitivity, 0.0).into();
-    }
-    if input.key_held(VirtualKeyCode::J) {
-      delta_orientation -= (sensitivity, 0.0).into();
-    }
+  // Shows the game state and applies the renderer settings the input changes
+  fn update(
+    input: &Input,
+    target: Option<RaycastHit>,
+    clock: &WorldClock,
+    renderer: &mut BloomRenderer,
+  ) {
+    // Slightly larger than the block, so the outline isn't hidden by it
+    let outline = target.map_or(vec![], |hit| {
+      let cell: Point3<f32> = hit.position().into();
+      let inflate = Vector3::from_value(0.002);
+      hit
+        .block
+        .block_type()
+        .model()
+        .collision_boxes()
+        .iter()
+        .map(|(min, max)| {
+          (cell + min.to_vec() - inflate, cell + max.to_vec() + inflate)
+        })
+        .collect()
+    });
+    renderer.set_block_outline(outline);
 
-    if input.key_pressed(VirtualKeyCode::LControl) {
-      println!("Camera: {}", camera);
+    let settings = &mut renderer.settings;
+    if input.pressed(Action::NextDebugView) {
+      settings.debug_view = settings.debug_view.next();
     }
-
-    if input.key_held(VirtualKeyCode::O) {
-      camera.inc_fovy(Deg(10.0 * delta));
+    if input.pressed(Action::ToggleDebug) {
+      settings.debug_panel = !settings.debug_panel;
     }
-    if input.key_held(VirtualKeyCode::P) {
-      camera.inc_fovy(Deg(-10.0 * delta));
+    // The transparency passes check the setting every frame
+    if input.pressed(Action::NextTransparency) {
+      settings.transparency = settings.transparency.next();
     }
-
-    if input.key_pressed(VirtualKeyCode::R) {
-      let block = block_registry.find_block("glass");
-      world.set_block((1, 1, 2).into(), Some(&block));
+    // Rebuilds the pipelines and render graph on the next frame
+    if input.pressed(Action::NextAntialiasing) {
+      settings.antialiasing = settings.antialiasing.next();
     }
-    if input.key_pressed(VirtualKeyCode::T) {
-      let block = block_registry.find_block("glass");
-      world.set_block((1, 1, 1).into(), Some(&block));
+    if input.pressed(Action::ToggleChunkBounds) {
+      settings.chunk_bounds = !settings.chunk_bounds;
     }
 
-    camera.displace(displacement);
-    camera.rotate(delta_orientation);
+    renderer.sky.set_time_of_day(clock.time_of_day());
+    renderer.lighting.set_sun_direction(clock.sun_direction());
+    renderer.lighting.set_sun_intensity(clock.sun_intensity());
+    renderer.lighting.set_ambient(clock.ambient());
   }
 }
 
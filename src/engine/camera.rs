@@ -1,65 +1,167 @@
+pub mod path;
+
 use std::fmt::Display;
 
-use cgmath::{perspective, Deg, InnerSpace, Matrix4, Point3, Vector3};
+use cgmath::{
+  ortho, perspective, Angle, Deg, InnerSpace, Matrix4, Point3, Rad,
+  SquareMatrix, Transform, Vector3,
+};
 use wgpu::{
   BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
   BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
-  BufferBindingType, BufferDescriptor, BufferUsages, Device, Queue,
-  ShaderStages,
+  BufferBindingType, BufferDescriptor, BufferUsages, CompareFunction, Device,
+  Queue, ShaderStages,
+};
+
+use super::math::{
+  Orientation2, Orientation3, OPENGL_TO_WGPU_MATRIX, REVERSE_Z_MATRIX,
 };
 
-use super::math::{Orientation2, OPENGL_TO_WGPU_MATRIX};
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+  Perspective { fovy: Deg<f32> },
+  // Parallel view rays, with `height` world units fitting vertically on
+  // screen. Meant for top down map renders
+  Orthographic { height: f32 },
+  // Orthographic, looking down along the diagonal of a block. The camera
+  // orientation only picks which of the four diagonals it looks along
+  Isometric { height: f32 },
+}
+
+impl Projection {
+  const DEFAULT_FOVY: Deg<f32> = Deg(45.0);
+  const DEFAULT_HEIGHT: f32 = 32.0;
+  const FOVY_RANGE: (f32, f32) = (1.0, 170.0);
+  const HEIGHT_RANGE: (f32, f32) = (1.0, 1024.0);
+
+  pub fn next(&self) -> Projection {
+    match *self {
+      Projection::Perspective { .. } => Projection::Orthographic {
+        height: Self::DEFAULT_HEIGHT,
+      },
+      Projection::Orthographic { height } => Projection::Isometric { height },
+      Projection::Isometric { .. } => Projection::Perspective {
+        fovy: Self::DEFAULT_FOVY,
+      },
+    }
+  }
+}
+
+// How depth values are spread between the near plane and the distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthRange {
+  // 0 at the near plane and 1 at the far plane
+  Standard,
+  // 1 at the near plane, going towards 0 infinitely far away. Floating point
+  // depth keeps far more precision in the distance this way, and perspective
+  // projections no longer clip anything past the far plane
+  ReverseInfinite,
+}
+
+impl DepthRange {
+  pub fn clear_value(&self) -> f32 {
+    match self {
+      DepthRange::Standard => 1.0,
+      DepthRange::ReverseInfinite => 0.0,
+    }
+  }
+
+  // Passes for fragments closer to the camera
+  pub fn compare(&self) -> CompareFunction {
+    match self {
+      DepthRange::Standard => CompareFunction::Less,
+      DepthRange::ReverseInfinite => CompareFunction::Greater,
+    }
+  }
+
+  pub fn compare_equal(&self) -> CompareFunction {
+    match self {
+      DepthRange::Standard => CompareFunction::LessEqual,
+      DepthRange::ReverseInfinite => CompareFunction::GreaterEqual,
+    }
+  }
+}
 
 pub struct Camera {
   position: Point3<f32>,
-  orientation: Orientation2,
+  orientation: Orientation3,
 
-  fovy: Deg<f32>,
+  projection: Projection,
+  depth_range: DepthRange,
   aspect: f32,
   znear: f32,
   zfar: f32,
+}
 
-  camera_buffer: Buffer,
-  pub camera_bind_group: BindGroup,
+// The view-projection matrix of the camera, on the GPU
+pub struct CameraUniform {
+  buffer: Buffer,
+  pub bind_group: BindGroup,
   // Save projection matrix each time it is rebuilt to avoid
   // unnecesarily sending it to the GPU each frame
   cached_proj_matrix: Option<[[f32; 4]; 4]>,
 }
 
-impl Camera {
+impl CameraUniform {
   pub fn new(
-    aspect: f32,
     camera_bind_group_layout: &BindGroupLayout,
     device: &Device,
   ) -> Self {
-    let camera_buffer = device.create_buffer(&BufferDescriptor {
+    let buffer = device.create_buffer(&BufferDescriptor {
       label: Some("camera_buffer"),
       size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
       usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
       mapped_at_creation: false,
     });
 
-    let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
       label: Some("camera_bind_group"),
       layout: camera_bind_group_layout,
       entries: &[BindGroupEntry {
         binding: 0,
-        resource: camera_buffer.as_entire_binding(),
+        resource: buffer.as_entire_binding(),
       }],
     });
 
+    Self {
+      buffer,
+      bind_group,
+      cached_proj_matrix: None,
+    }
+  }
+
+  pub fn update(&mut self, camera: &Camera, queue: &Queue) {
+    let matrix = camera.gpu_view_proj_matrix();
+
+    let buffer_content: [[f32; 4]; 4] = matrix.into();
+
+    if self.cached_proj_matrix.is_none()
+      || self.cached_proj_matrix.unwrap() != buffer_content
+    {
+      self.cached_proj_matrix = Some(buffer_content);
+
+      queue.write_buffer(
+        &self.buffer,
+        0,
+        bytemuck::cast_slice(&[buffer_content]),
+      );
+    }
+  }
+}
+
+impl Camera {
+  pub fn new(aspect: f32) -> Self {
     Self {
       position: (0.0, 2.0, 3.0).into(),
-      orientation: (90.0, 45.0).into(),
+      orientation: Orientation2::from((90.0, 45.0)).into(),
 
-      fovy: Deg(45.0),
+      projection: Projection::Perspective {
+        fovy: Projection::DEFAULT_FOVY,
+      },
+      depth_range: DepthRange::Standard,
       aspect,
       znear: 0.1,
       zfar: 100.0,
-
-      camera_buffer,
-      camera_bind_group,
-      cached_proj_matrix: None,
     }
   }
 
@@ -83,72 +185,196 @@ impl Camera {
     }
   }
 
-  pub fn update_proj_matrix(&mut self, queue: &Queue) {
-    let view =
-      Matrix4::look_to_lh(self.position, self.forward(), Vector3::unit_y());
-    let proj = perspective(self.fovy, self.aspect, self.znear, self.zfar);
-    let matrix = OPENGL_TO_WGPU_MATRIX * proj * view;
+  fn view_proj_matrix(&self, znear: f32, zfar: f32) -> Matrix4<f32> {
+    let view = Matrix4::look_to_lh(self.position, self.forward(), self.up());
+    let proj = match self.projection {
+      Projection::Perspective { fovy } => {
+        perspective(fovy, self.aspect, znear, zfar)
+      }
+      Projection::Orthographic { height }
+      | Projection::Isometric { height } => {
+        let (half_width, half_height) =
+          (height * self.aspect / 2.0, height / 2.0);
+        ortho(
+          -half_width,
+          half_width,
+          -half_height,
+          half_height,
+          znear,
+          zfar,
+        )
+      }
+    };
+    OPENGL_TO_WGPU_MATRIX * proj * view
+  }
 
-    let buffer_content: [[f32; 4]; 4] = matrix.into();
+  pub fn inverse_view_proj_matrix(&self) -> Matrix4<f32> {
+    self
+      .view_proj_matrix(self.znear, self.zfar)
+      .invert()
+      .unwrap()
+  }
 
-    if self.cached_proj_matrix.is_none()
-      || self.cached_proj_matrix.unwrap() != buffer_content
-    {
-      self.cached_proj_matrix = Some(buffer_content);
+  // World space corners of the part of the view frustum between the
+  // distances `znear` and `zfar` from the camera
+  pub fn frustum_corners(&self, znear: f32, zfar: f32) -> [Point3<f32>; 8] {
+    let inverse = self.view_proj_matrix(znear, zfar).invert().unwrap();
+    let mut corners = [Point3::new(0.0, 0.0, 0.0); 8];
+    corners.iter_mut().enumerate().for_each(|(i, corner)| {
+      let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+      let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+      let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+      *corner = inverse.transform_point(Point3::new(x, y, z));
+    });
+    corners
+  }
 
-      queue.write_buffer(
-        &self.camera_buffer,
-        0,
-        bytemuck::cast_slice(&[buffer_content]),
-      );
+  // What the camera uniform holds, unlike view_proj_matrix it depends on the
+  // depth range
+  fn gpu_view_proj_matrix(&self) -> Matrix4<f32> {
+    match (self.depth_range, self.projection) {
+      (DepthRange::Standard, _) => self.view_proj_matrix(self.znear, self.zfar),
+      (DepthRange::ReverseInfinite, Projection::Perspective { fovy }) => {
+        let view =
+          Matrix4::look_to_lh(self.position, self.forward(), self.up());
+        let f = 1.0 / (Rad::from(fovy) / 2.0).tan();
+        // Depth is znear / distance, the view space z being -distance
+        #[rustfmt::skip]
+        let proj = Matrix4::new(
+          f / self.aspect, 0.0, 0.0, 0.0,
+          0.0, f, 0.0, 0.0,
+          0.0, 0.0, 0.0, -1.0,
+          0.0, 0.0, self.znear, 0.0,
+        );
+        proj * view
+      }
+      // Orthographic projections have nothing to gain from an infinite far
+      // plane, only the depth values get flipped
+      (DepthRange::ReverseInfinite, _) => {
+        REVERSE_Z_MATRIX * self.view_proj_matrix(self.znear, self.zfar)
+      }
     }
   }
+
   pub fn update_aspect(&mut self, aspect: f32) {
     self.aspect = aspect;
   }
-  pub fn inc_fovy(&mut self, delta_fovy: Deg<f32>) {
-    self.fovy += delta_fovy;
+  pub fn projection(&self) -> Projection {
+    self.projection
+  }
+  pub fn set_projection(&mut self, projection: Projection) {
+    self.projection = projection;
+  }
+  // Factors above 1 zoom in, narrowing the field of view of perspective
+  // projections and showing less of the world in orthographic ones
+  pub fn zoom(&mut self, factor: f32) {
+    match &mut self.projection {
+      Projection::Perspective { fovy } => {
+        let (min, max) = Projection::FOVY_RANGE;
+        *fovy = Deg((fovy.0 / factor).clamp(min, max));
+      }
+      Projection::Orthographic { height }
+      | Projection::Isometric { height } => {
+        let (min, max) = Projection::HEIGHT_RANGE;
+        *height = (*height / factor).clamp(min, max);
+      }
+    }
+  }
+
+  pub fn depth_range(&self) -> DepthRange {
+    self.depth_range
+  }
+  pub fn set_depth_range(&mut self, depth_range: DepthRange) {
+    self.depth_range = depth_range;
+  }
+
+  pub fn znear(&self) -> f32 {
+    self.znear
+  }
+  pub fn zfar(&self) -> f32 {
+    self.zfar
+  }
+
+  // Where the camera actually looks, isometric projections snap the
+  // orientation to the closest block diagonal
+  fn view_orientation(&self) -> Orientation3 {
+    match self.projection {
+      Projection::Isometric { .. } => {
+        let heading = Orientation2::from(self.orientation).pitch.0;
+        let heading = ((heading - 45.0) / 90.0).round() * 90.0 + 45.0;
+        let elevation = Deg::atan(1.0 / 2.0f32.sqrt());
+        Orientation2::new(Deg(heading), elevation).into()
+      }
+      _ => self.orientation,
+    }
   }
 
   pub fn forward(&self) -> Vector3<f32> {
-    self.orientation.direction()
+    self.view_orientation().forward()
   }
   pub fn up(&self) -> Vector3<f32> {
-    Vector3::unit_y()
+    self.view_orientation().up()
   }
   pub fn left(&self) -> Vector3<f32> {
     self.forward().cross(self.up()).normalize()
   }
+  // The view matrix is left handed, so the camera looks towards -forward
+  pub fn view_direction(&self) -> Vector3<f32> {
+    -self.forward()
+  }
   pub fn displace(&mut self, delta: Vector3<f32>) {
     self.position += delta;
   }
+  pub fn set_position(&mut self, position: Point3<f32>) {
+    self.position = position;
+  }
+  // Looking up or down stops short of the vertical, where turning around
+  // the vertical axis would spin the view
   pub fn rotate(&mut self, delta: Orientation2) {
-    self.orientation += delta;
-    if self.orientation.yaw > Deg(85.0) {
-      self.orientation.yaw = Deg(85.0);
-    } else if self.orientation.yaw < Deg(-85.0) {
-      self.orientation.yaw = Deg(-85.0);
-    }
+    let rotated = self.orientation.rotated(delta);
+    self.orientation = if rotated.forward().y.abs() <= Deg(85.0).sin() {
+      rotated
+    } else {
+      self
+        .orientation
+        .rotated(Orientation2::new(delta.pitch, Deg(0.0)))
+    };
+  }
+  pub fn roll(&mut self, angle: Deg<f32>) {
+    self.orientation = self.orientation.rolled(angle);
+  }
+  pub fn set_orientation(&mut self, orientation: Orientation3) {
+    self.orientation = orientation;
+  }
+  // The camera looks towards -forward, so forward points away from the target
+  pub fn look_at(&mut self, target: Point3<f32>) {
+    self.orientation =
+      Orientation3::look_to(self.position - target, Vector3::unit_y());
   }
 
-  // pub fn position(&self) -> Point3<f32> {
-  //   self.position
-  // }
+  pub fn position(&self) -> Point3<f32> {
+    self.position
+  }
+  pub fn orientation(&self) -> Orientation3 {
+    self.orientation
+  }
 }
 
 impl Display for Camera {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let orientation = Orientation2::from(self.orientation);
     let forward = self.forward();
     let left = self.left();
     write!(
       f,
-      "position=(x={:.2},y={:.2},z={:.2}),orientation=(pitch={:.2?},yaw={:.2?}),fovy={:.2?},forward=(x={:.2},y={:.2},z={:.2}),forward_mag={:.2},left=(x={:.2},y={:.2},z={:.2}),left_mag={:.2}",
+      "position=(x={:.2},y={:.2},z={:.2}),orientation=(pitch={:.2?},yaw={:.2?},roll={:.2?}),projection={:.2?},forward=(x={:.2},y={:.2},z={:.2}),forward_mag={:.2},left=(x={:.2},y={:.2},z={:.2}),left_mag={:.2}",
       self.position.x,
       self.position.y,
       self.position.z,
-      self.orientation.pitch,
-      self.orientation.yaw,
-      self.fovy,
+      orientation.pitch,
+      orientation.yaw,
+      self.orientation.roll(),
+      self.projection,
       forward.x,
       forward.y,
       forward.z,
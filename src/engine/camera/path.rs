@@ -0,0 +1,402 @@
+use std::{
+  fs::File,
+  io::{BufRead, BufReader, BufWriter, Write},
+  path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Vector3};
+
+use crate::engine::math::Orientation3;
+
+use super::Camera;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+  pub time: f32, // Seconds from the start of the path
+  pub position: Point3<f32>,
+  pub orientation: Orientation3,
+}
+
+impl Keyframe {
+  pub fn of_camera(time: f32, camera: &Camera) -> Self {
+    Self {
+      time,
+      position: camera.position(),
+      orientation: camera.orientation(),
+    }
+  }
+}
+
+// Smooth camera movement through keyframes. Positions follow a Catmull-Rom
+// spline, which passes through every keyframe, and orientations are
+// interpolated between consecutive keyframes
+pub struct CameraPath {
+  keyframes: Vec<Keyframe>, // Sorted by time
+}
+
+impl CameraPath {
+  pub fn new() -> Self {
+    Self { keyframes: vec![] }
+  }
+
+  pub fn push(&mut self, keyframe: Keyframe) {
+    let index = self
+      .keyframes
+      .partition_point(|other| other.time <= keyframe.time);
+    self.keyframes.insert(index, keyframe);
+  }
+
+  pub fn keyframes(&self) -> &[Keyframe] {
+    &self.keyframes
+  }
+
+  pub fn duration(&self) -> f32 {
+    self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+  }
+
+  // Camera position and orientation at the given time, clamped to the
+  // time span of the path
+  pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Orientation3)> {
+    let first = self.keyframes.first()?;
+    let last = self.keyframes.last()?;
+    if time <= first.time {
+      return Some((first.position, first.orientation));
+    }
+    if time >= last.time {
+      return Some((last.position, last.orientation));
+    }
+
+    // Segment between keyframes i and i + 1
+    let i = self.keyframes.partition_point(|other| other.time <= time) - 1;
+    let (k1, k2) = (&self.keyframes[i], &self.keyframes[i + 1]);
+    let k0 = &self.keyframes[i.saturating_sub(1)];
+    let k3 = &self.keyframes[(i + 2).min(self.keyframes.len() - 1)];
+    let span = k2.time - k1.time;
+    let t = if span > 0.0 {
+      (time - k1.time) / span
+    } else {
+      1.0
+    };
+
+    let position = catmull_rom(
+      k0.position.to_vec(),
+      k1.position.to_vec(),
+      k2.position.to_vec(),
+      k3.position.to_vec(),
+      t,
+    );
+    let orientation = k1.orientation.slerp(k2.orientation, t);
+    Some((Point3::from_vec(position), orientation))
+  }
+
+  // Text file with a keyframe per line, made of its time, position and
+  // orientation quaternion. Empty lines and lines starting with # are skipped
+  pub fn load(path: &Path) -> Result<Self> {
+    let mut camera_path = Self::new();
+    let reader = BufReader::new(File::open(path)?);
+    for (number, line) in reader.lines().enumerate() {
+      let line = line?;
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let values = line
+        .split_whitespace()
+        .map(str::parse::<f32>)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("{}:{}", path.display(), number + 1))?;
+      let [time, x, y, z, w, i, j, k] = values[..] else {
+        bail!(
+          "{}:{}: expected 8 values, found {}",
+          path.display(),
+          number + 1,
+          values.len()
+        );
+      };
+      // Rust parses nan and inf, either would end up in the camera
+      if let Some(value) = values.iter().find(|value| !value.is_finite()) {
+        bail!("{}:{}: {} is not finite", path.display(), number + 1, value);
+      }
+      let rotation = Quaternion::new(w, i, j, k);
+      // Normalizing it would give NaN
+      if rotation.magnitude2() < 1e-12 {
+        bail!("{}:{}: rotation of length zero", path.display(), number + 1);
+      }
+      camera_path.push(Keyframe {
+        time,
+        position: Point3::new(x, y, z),
+        orientation: Orientation3::from_quaternion(rotation),
+      });
+    }
+    Ok(camera_path)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "# time x y z qw qx qy qz")?;
+    for keyframe in &self.keyframes {
+      let position = keyframe.position;
+      let rotation = keyframe.orientation.quaternion();
+      writeln!(
+        file,
+        "{} {} {} {} {} {} {} {}",
+        keyframe.time,
+        position.x,
+        position.y,
+        position.z,
+        rotation.s,
+        rotation.v.x,
+        rotation.v.y,
+        rotation.v.z,
+      )?;
+    }
+    file.flush()?;
+    Ok(())
+  }
+}
+
+// Point between p1 (t = 0) and p2 (t = 1), with p0 and p3 shaping the curve
+fn catmull_rom(
+  p0: Vector3<f32>,
+  p1: Vector3<f32>,
+  p2: Vector3<f32>,
+  p3: Vector3<f32>,
+  t: f32,
+) -> Vector3<f32> {
+  let (t2, t3) = (t * t, t * t * t);
+  (p1 * 2.0
+    + (p2 - p0) * t
+    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+    * 0.5
+}
+
+// Samples the camera at a fixed interval while recording. The samples are
+// the keyframes of a path, so recordings are played back like any other path
+pub struct CameraRecorder {
+  path: CameraPath,
+  time: f32,
+  last_sample: Option<f32>,
+}
+
+impl CameraRecorder {
+  const INTERVAL: f32 = 1.0 / 30.0; // Seconds
+
+  pub fn new() -> Self {
+    Self {
+      path: CameraPath::new(),
+      time: 0.0,
+      last_sample: None,
+    }
+  }
+
+  // The first call records the starting point of the path
+  pub fn record(&mut self, delta: f32, camera: &Camera) {
+    if self.last_sample.is_some() {
+      self.time += delta;
+    }
+    if self
+      .last_sample
+      .map_or(true, |last| self.time - last >= Self::INTERVAL)
+    {
+      self.path.push(Keyframe::of_camera(self.time, camera));
+      self.last_sample = Some(self.time);
+    }
+  }
+
+  // Where recordings are saved, from the BLOOM_CAMERA_RECORD environment
+  // variable
+  pub fn file_from_env() -> PathBuf {
+    std::env::var_os("BLOOM_CAMERA_RECORD")
+      .map(PathBuf::from)
+      .unwrap_or_else(|| PathBuf::from("camera_path.txt"))
+  }
+
+  pub fn finish(self) -> CameraPath {
+    self.path
+  }
+}
+
+// Moves the camera along a path. The BLOOM_CAMERA_STEP environment variable
+// makes every update advance by that many seconds instead of the real frame
+// time, so flythroughs render the same frames on any machine
+pub struct CameraPlayback {
+  path: CameraPath,
+  time: f32,
+  step: Option<f32>,
+}
+
+impl CameraPlayback {
+  pub fn new(path: CameraPath) -> Self {
+    let step = std::env::var("BLOOM_CAMERA_STEP")
+      .ok()
+      .and_then(|step| step.parse().ok());
+    Self {
+      path,
+      time: 0.0,
+      step,
+    }
+  }
+
+  // Plays the path in the file the BLOOM_CAMERA_PATH environment variable
+  // names, if any
+  pub fn from_env() -> Result<Option<Self>> {
+    match std::env::var_os("BLOOM_CAMERA_PATH") {
+      Some(file) => {
+        Ok(Some(Self::new(CameraPath::load(&PathBuf::from(file))?)))
+      }
+      None => Ok(None),
+    }
+  }
+
+  // False once the end of the path was reached
+  pub fn advance(&mut self, delta: f32, camera: &mut Camera) -> bool {
+    if let Some((position, orientation)) = self.path.sample(self.time) {
+      camera.set_position(position);
+      camera.set_orientation(orientation);
+    }
+    let playing = self.time < self.path.duration();
+    self.time += self.step.unwrap_or(delta);
+    playing
+  }
+
+  pub fn into_path(self) -> CameraPath {
+    self.path
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Deg, Rotation3};
+
+  use super::*;
+
+  const EPSILON: f32 = 1e-4;
+
+  fn keyframe(time: f32, position: (f32, f32, f32), yaw: f32) -> Keyframe {
+    Keyframe {
+      time,
+      position: position.into(),
+      orientation: Orientation3::from_quaternion(Quaternion::from_angle_y(
+        Deg(yaw),
+      )),
+    }
+  }
+
+  fn path() -> CameraPath {
+    let mut path = CameraPath::new();
+    path.push(keyframe(0.0, (0.0, 0.0, 0.0), 0.0));
+    path.push(keyframe(1.0, (4.0, 1.0, -2.0), 30.0));
+    path.push(keyframe(2.5, (5.0, 3.0, 0.5), 90.0));
+    path.push(keyframe(3.0, (-1.0, 2.0, 7.0), 45.0));
+    path
+  }
+
+  fn assert_near(a: Point3<f32>, b: Point3<f32>) {
+    assert!((a - b).magnitude() < EPSILON, "{:?} != {:?}", a, b);
+  }
+
+  fn assert_same_orientation(a: Orientation3, b: Orientation3) {
+    // q and -q are the same rotation
+    let dot = a.quaternion().dot(b.quaternion()).abs();
+    assert!((dot - 1.0).abs() < EPSILON, "{:?} != {:?}", a, b);
+  }
+
+  fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+      "bloom_{}_{}.txt",
+      name,
+      std::process::id()
+    ))
+  }
+
+  #[test]
+  fn catmull_rom_goes_through_the_inner_points() {
+    let p = [
+      Vector3::new(-1.0, 0.0, 2.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(3.0, -2.0, 1.0),
+      Vector3::new(4.0, 4.0, 4.0),
+    ];
+    let start = catmull_rom(p[0], p[1], p[2], p[3], 0.0);
+    let end = catmull_rom(p[0], p[1], p[2], p[3], 1.0);
+    assert!((start - p[1]).magnitude() < EPSILON);
+    assert!((end - p[2]).magnitude() < EPSILON);
+  }
+
+  #[test]
+  fn samples_pass_through_every_keyframe() {
+    let path = path();
+    path.keyframes().iter().for_each(|keyframe| {
+      let (position, orientation) = path.sample(keyframe.time).unwrap();
+      assert_near(position, keyframe.position);
+      assert_same_orientation(orientation, keyframe.orientation);
+    });
+  }
+
+  #[test]
+  fn samples_are_clamped_to_the_path() {
+    let path = path();
+    let (first, last) = (path.keyframes()[0], path.keyframes()[3]);
+    for time in [-1.0, -0.001, f32::NEG_INFINITY] {
+      let (position, orientation) = path.sample(time).unwrap();
+      assert_near(position, first.position);
+      assert_same_orientation(orientation, first.orientation);
+    }
+    for time in [3.001, 10.0, f32::INFINITY] {
+      let (position, orientation) = path.sample(time).unwrap();
+      assert_near(position, last.position);
+      assert_same_orientation(orientation, last.orientation);
+    }
+    assert!(CameraPath::new().sample(0.0).is_none());
+  }
+
+  #[test]
+  fn saved_paths_load_back() -> Result<()> {
+    let path = path();
+    let file = temp_file("camera_path");
+    path.save(&file)?;
+    let loaded = CameraPath::load(&file);
+    std::fs::remove_file(&file)?;
+    let loaded = loaded?;
+    assert_eq!(loaded.keyframes().len(), path.keyframes().len());
+    path.keyframes().iter().zip(loaded.keyframes()).for_each(
+      |(saved, loaded)| {
+        assert_eq!(saved.time, loaded.time);
+        assert_near(saved.position, loaded.position);
+        assert_same_orientation(saved.orientation, loaded.orientation);
+      },
+    );
+    Ok(())
+  }
+
+  // The error of loading a file made of the given lines
+  fn load_error(lines: &str) -> String {
+    let file = temp_file("bad_camera_path");
+    std::fs::write(&file, lines).unwrap();
+    let result = CameraPath::load(&file);
+    std::fs::remove_file(&file).unwrap();
+    format!("{:#}", result.err().expect("the file should be rejected"))
+  }
+
+  #[test]
+  fn non_finite_values_are_rejected() {
+    let valid = "0 0 0 0 1 0 0 0\n";
+    for bad in [
+      "1 nan 0 0 1 0 0 0",
+      "1 0 inf 0 1 0 0 0",
+      "NaN 0 0 0 1 0 0 0",
+      "1 0 0 0 1 -inf 0 0",
+    ] {
+      let error = load_error(&format!("# header\n{}{}\n", valid, bad));
+      assert!(error.contains(":3:"), "{}", error);
+    }
+  }
+
+  #[test]
+  fn zero_rotations_are_rejected() {
+    let error = load_error("0 0 0 0 1 0 0 0\n1 0 0 0 0 0 0 0\n");
+    assert!(error.contains(":2:"), "{}", error);
+  }
+}
@@ -0,0 +1,498 @@
+use cgmath::{Deg, InnerSpace, Point3, Vector3, Zero};
+use winit::window::{CursorGrabMode, Window};
+
+use crate::input::{action::Action, Input};
+
+use super::{
+  camera::Camera,
+  game::{block::instance::BlockPosition, world::World},
+  math::Orientation2,
+};
+
+pub struct ControllerSettings {
+  pub sensitivity: f32, // Degrees per pixel of mouse motion
+  pub invert_y: bool,
+  pub move_speed: f32, // Blocks per second
+}
+
+impl Default for ControllerSettings {
+  fn default() -> Self {
+    Self {
+      sensitivity: 0.15,
+      invert_y: false,
+      move_speed: 7.0,
+    }
+  }
+}
+
+impl ControllerSettings {
+  // Rotation of the camera for the given mouse motion, in pixels
+  pub fn look(&self, motion: (f32, f32)) -> Orientation2 {
+    let (dx, dy) = motion;
+    let dy = if self.invert_y { -dy } else { dy };
+    Orientation2::new(Deg(dx * self.sensitivity), Deg(dy * self.sensitivity))
+  }
+}
+
+// Moves the camera from the player's input, once per update
+pub trait CameraController {
+  fn name(&self) -> &'static str;
+  fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    look: Orientation2,
+    settings: &ControllerSettings,
+    world: &World,
+    camera: &mut Camera,
+  );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControllerKind {
+  FreeFly,
+  Orbit,
+  Walk,
+}
+
+impl ControllerKind {
+  pub fn next(&self) -> ControllerKind {
+    match self {
+      ControllerKind::FreeFly => ControllerKind::Orbit,
+      ControllerKind::Orbit => ControllerKind::Walk,
+      ControllerKind::Walk => ControllerKind::FreeFly,
+    }
+  }
+
+  // Starts from where the camera currently is, so switching is seamless
+  pub fn create(&self, camera: &Camera) -> Box<dyn CameraController> {
+    match self {
+      ControllerKind::FreeFly => Box::new(FreeFly),
+      ControllerKind::Orbit => Box::new(Orbit::around_view(camera, 8.0)),
+      ControllerKind::Walk => Box::new(Walk::new()),
+    }
+  }
+}
+
+// Movement actions relative to the camera, (right, up, backward)
+fn movement(input: &Input) -> Vector3<f32> {
+  Vector3::new(
+    input.axis(Action::MoveRight, Action::MoveLeft),
+    input.axis(Action::MoveUp, Action::MoveDown),
+    input.axis(Action::MoveBackward, Action::MoveForward),
+  )
+}
+
+// Flies in the direction the camera looks, without any collision. Rolls the
+// camera too, for cinematic shots
+pub struct FreeFly;
+
+impl FreeFly {
+  const ROLL_SPEED: f32 = 45.0; // Degrees per second
+}
+
+impl CameraController for FreeFly {
+  fn name(&self) -> &'static str {
+    "free fly"
+  }
+
+  fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    look: Orientation2,
+    settings: &ControllerSettings,
+    _world: &World,
+    camera: &mut Camera,
+  ) {
+    let movement = movement(input);
+    let displacement = camera.forward() * movement.z
+      - camera.left() * movement.x
+      + camera.up() * movement.y;
+    camera.displace(displacement * settings.move_speed * delta);
+    camera.rotate(look);
+
+    let roll = input.axis(Action::RollRight, Action::RollLeft);
+    camera.roll(Deg(roll * Self::ROLL_SPEED * delta));
+  }
+}
+
+// Turns around a fixed point, moving forward and backward get closer to it
+// and further away and the other movements move the point itself
+pub struct Orbit {
+  target: Point3<f32>,
+  distance: f32,
+}
+
+impl Orbit {
+  const MIN_DISTANCE: f32 = 1.0;
+
+  pub fn new(target: Point3<f32>, distance: f32) -> Self {
+    Self {
+      target,
+      distance: distance.max(Self::MIN_DISTANCE),
+    }
+  }
+
+  // Orbits the point `distance` in front of the camera
+  pub fn around_view(camera: &Camera, distance: f32) -> Self {
+    Self::new(
+      camera.position() + camera.view_direction() * distance,
+      distance,
+    )
+  }
+
+  pub fn target(&self) -> Point3<f32> {
+    self.target
+  }
+  pub fn set_target(&mut self, target: Point3<f32>) {
+    self.target = target;
+  }
+}
+
+impl CameraController for Orbit {
+  fn name(&self) -> &'static str {
+    "orbit"
+  }
+
+  fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    look: Orientation2,
+    settings: &ControllerSettings,
+    _world: &World,
+    camera: &mut Camera,
+  ) {
+    let movement = movement(input) * settings.move_speed * delta;
+    self.distance = (self.distance + movement.z).max(Self::MIN_DISTANCE);
+    self.target += -camera.left() * movement.x + Vector3::unit_y() * movement.y;
+
+    camera.rotate(look);
+    // The camera looks towards -forward, so it sits on the forward side
+    camera.set_position(self.target + camera.forward() * self.distance);
+  }
+}
+
+// Walks on the ground, stopping against blocks and falling where there is
+// none. Looking up or down does not change where the camera goes, jumping
+// is the only way up
+pub struct Walk {
+  vertical_speed: f32, // Blocks per second, upwards
+  grounded: bool,
+}
+
+impl Walk {
+  const SPRINT: f32 = 1.8;
+  // Body of the walker, a box around the camera
+  const EYE_HEIGHT: f32 = 1.6; // Above the feet
+  const HEIGHT: f32 = 1.8;
+  const RADIUS: f32 = 0.3; // Half the width
+  const GRAVITY: f32 = 25.0; // Blocks per second squared
+  const MAX_FALL_SPEED: f32 = 50.0; // Blocks per second
+  const JUMP_SPEED: f32 = 8.0; // Blocks per second
+
+  pub fn new() -> Self {
+    Self {
+      vertical_speed: 0.0,
+      grounded: false,
+    }
+  }
+
+  fn body(feet: Point3<f32>) -> (Point3<f32>, Point3<f32>) {
+    (
+      feet - Vector3::new(Self::RADIUS, 0.0, Self::RADIUS),
+      feet + Vector3::new(Self::RADIUS, Self::HEIGHT, Self::RADIUS),
+    )
+  }
+}
+
+impl CameraController for Walk {
+  fn name(&self) -> &'static str {
+    "walk"
+  }
+
+  fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    look: Orientation2,
+    settings: &ControllerSettings,
+    world: &World,
+    camera: &mut Camera,
+  ) {
+    let movement = movement(input);
+    let mut forward = camera.forward();
+    forward.y = 0.0;
+    let forward = if forward.is_zero() {
+      forward
+    } else {
+      forward.normalize()
+    };
+    let left = forward.cross(Vector3::unit_y());
+    let mut displacement = forward * movement.z - left * movement.x;
+    if !displacement.is_zero() {
+      displacement = displacement.normalize();
+    }
+    let speed = if input.held(Action::Sprint) {
+      settings.move_speed * Self::SPRINT
+    } else {
+      settings.move_speed
+    };
+    displacement *= speed * delta;
+
+    if self.grounded && movement.y > 0.0 {
+      self.vertical_speed = Self::JUMP_SPEED;
+    }
+    self.vertical_speed =
+      (self.vertical_speed - Self::GRAVITY * delta).max(-Self::MAX_FALL_SPEED);
+    displacement.y = self.vertical_speed * delta;
+
+    let mut feet = camera.position() - Vector3::unit_y() * Self::EYE_HEIGHT;
+    let (min, max) = Self::body(feet);
+    let reach = Vector3::new(
+      displacement.x.abs(),
+      displacement.y.abs(),
+      displacement.z.abs(),
+    );
+    let boxes = collision_boxes(world, min - reach, max + reach);
+    // Vertically first, so walking off a ledge doesn't clip its side
+    for axis in [1, 0, 2] {
+      let moved = clip(Self::body(feet), &boxes, axis, displacement[axis]);
+      feet[axis] += moved;
+      if axis == 1 {
+        self.grounded = displacement.y < 0.0 && moved > displacement.y;
+        if moved != displacement.y {
+          self.vertical_speed = 0.0;
+        }
+      }
+    }
+    camera.set_position(feet + Vector3::unit_y() * Self::EYE_HEIGHT);
+    camera.rotate(look);
+  }
+}
+
+// Collision boxes of the blocks in the region, in world space
+fn collision_boxes(
+  world: &World,
+  min: Point3<f32>,
+  max: Point3<f32>,
+) -> Vec<(Point3<f32>, Point3<f32>)> {
+  let (min, max) =
+    (min.map(|c| c.floor() as i32), max.map(|c| c.floor() as i32));
+  let mut boxes = vec![];
+  for x in min.x..=max.x {
+    for y in min.y..=max.y {
+      for z in min.z..=max.z {
+        let position = BlockPosition { x, y, z };
+        let Some(block) = world.block_at(position) else {
+          continue;
+        };
+        let cell = Vector3::new(x as f32, y as f32, z as f32);
+        boxes.extend(
+          block
+            .block_type()
+            .model()
+            .collision_boxes()
+            .iter()
+            .map(|(min, max)| (*min + cell, *max + cell)),
+        );
+      }
+    }
+  }
+  boxes
+}
+
+// How far the body can move along the axis, up to `distance`, before one of
+// the boxes stops it. Boxes it already overlaps don't, so it can get out
+fn clip(
+  (min, max): (Point3<f32>, Point3<f32>),
+  boxes: &[(Point3<f32>, Point3<f32>)],
+  axis: usize,
+  distance: f32,
+) -> f32 {
+  // Touching a box isn't overlapping it, despite rounding errors
+  const EPSILON: f32 = 1e-4;
+  boxes.iter().fold(distance, |distance, (box_min, box_max)| {
+    let beside = (0..3).filter(|other| *other != axis).any(|other| {
+      box_max[other] <= min[other] + EPSILON
+        || box_min[other] >= max[other] - EPSILON
+    });
+    if beside {
+      distance
+    } else if distance > 0.0 && box_min[axis] >= max[axis] - EPSILON {
+      distance.min(box_min[axis] - max[axis])
+    } else if distance < 0.0 && box_max[axis] <= min[axis] + EPSILON {
+      distance.max(box_max[axis] - min[axis])
+    } else {
+      distance
+    }
+  })
+}
+
+// Hides the cursor and keeps it in the window while looking around with the
+// mouse. Not every platform supports both grab modes, so falls back from one
+// to the other
+pub fn set_cursor_grab(window: &Window, grabbed: bool) {
+  let result = if grabbed {
+    window
+      .set_cursor_grab(CursorGrabMode::Locked)
+      .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+  } else {
+    window.set_cursor_grab(CursorGrabMode::None)
+  };
+  if let Err(error) = result {
+    eprintln!("Could not change the cursor grab: {}", error);
+  }
+  window.set_cursor_visible(!grabbed);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use winit::keyboard::KeyCode;
+
+  use super::*;
+  use crate::{
+    engine::game::block::{model::BlockModel, Block},
+    input::{
+      action::{ActionMap, Button},
+      InputEvent,
+    },
+  };
+
+  const DELTA: f32 = 1.0 / 60.0;
+  const EPSILON: f32 = 1e-3;
+
+  // Stone floor, its top at y = 1, with a wall across it at x = 5
+  fn room() -> World {
+    let stone = Rc::new(Block::without_material(
+      "stone",
+      &Rc::new(BlockModel::model_simple()),
+    ));
+    let mut world = World::new();
+    for x in -5..10 {
+      for z in -5..5 {
+        world.set_block((x, 0, z).into(), Some(&stone));
+      }
+    }
+    for y in 1..4 {
+      for z in -5..5 {
+        world.set_block((5, y, z).into(), Some(&stone));
+      }
+    }
+    world
+  }
+
+  // Looking along +x, its feet half a block above the floor
+  fn camera_at(x: f32) -> Camera {
+    let mut camera = Camera::new(1.0);
+    camera.set_position(Point3::new(x, 1.5 + Walk::EYE_HEIGHT, 0.5));
+    camera.look_at(Point3::new(x + 10.0, 1.5 + Walk::EYE_HEIGHT, 0.5));
+    camera
+  }
+
+  fn holding(keys: &[KeyCode]) -> Input {
+    let mut input = Input::new(ActionMap::default());
+    keys.iter().for_each(|code| {
+      input.apply(InputEvent::Button {
+        button: Button::Key(*code),
+        pressed: true,
+      })
+    });
+    input
+  }
+
+  fn walk(
+    walk: &mut Walk,
+    input: &Input,
+    world: &World,
+    camera: &mut Camera,
+    seconds: f32,
+  ) {
+    for _ in 0..(seconds / DELTA) as usize {
+      let look = Orientation2::new(Deg(0.0), Deg(0.0));
+      let settings = ControllerSettings::default();
+      walk.update(DELTA, input, look, &settings, world, camera);
+    }
+  }
+
+  #[test]
+  fn falls_onto_the_ground() {
+    let world = room();
+    let mut camera = camera_at(0.5);
+    let mut walker = Walk::new();
+    walk(&mut walker, &holding(&[]), &world, &mut camera, 1.0);
+    assert!((camera.position().y - (1.0 + Walk::EYE_HEIGHT)).abs() < EPSILON);
+    assert!(walker.grounded);
+  }
+
+  #[test]
+  fn walls_stop_the_walker() {
+    let world = room();
+    let mut camera = camera_at(0.5);
+    let mut walker = Walk::new();
+    let forward = holding(&[KeyCode::KeyW]);
+    walk(&mut walker, &forward, &world, &mut camera, 2.0);
+    let position = camera.position();
+    assert!((position.x - (5.0 - Walk::RADIUS)).abs() < EPSILON);
+    assert!((position.y - (1.0 + Walk::EYE_HEIGHT)).abs() < EPSILON);
+    assert!((position.z - 0.5).abs() < EPSILON);
+  }
+
+  #[test]
+  fn walking_off_the_edge_falls() {
+    let world = room();
+    let mut camera = camera_at(0.5);
+    // Backwards, towards the edge of the floor at x = -5
+    let backward = holding(&[KeyCode::KeyS]);
+    let mut walker = Walk::new();
+    walk(&mut walker, &backward, &world, &mut camera, 3.0);
+    assert!(camera.position().x < -5.0 - Walk::RADIUS);
+    assert!(camera.position().y < 1.0);
+    assert!(!walker.grounded);
+  }
+
+  #[test]
+  fn jumps_and_lands_back() {
+    let world = room();
+    let mut camera = camera_at(0.5);
+    let mut walker = Walk::new();
+    walk(&mut walker, &holding(&[]), &world, &mut camera, 1.0);
+    let ground = camera.position().y;
+    walk(
+      &mut walker,
+      &holding(&[KeyCode::Space]),
+      &world,
+      &mut camera,
+      DELTA,
+    );
+    walk(&mut walker, &holding(&[]), &world, &mut camera, 0.2);
+    assert!(camera.position().y > ground + 0.5);
+    walk(&mut walker, &holding(&[]), &world, &mut camera, 1.0);
+    assert!((camera.position().y - ground).abs() < EPSILON);
+  }
+
+  #[test]
+  fn ceilings_stop_jumps() {
+    let mut world = room();
+    let stone = Rc::new(Block::without_material(
+      "stone",
+      &Rc::new(BlockModel::model_simple()),
+    ));
+    // Two blocks above the floor, the body just fits under it
+    world.set_block((0, 3, 0).into(), Some(&stone));
+    let mut camera = camera_at(0.5);
+    let mut walker = Walk::new();
+    walk(&mut walker, &holding(&[]), &world, &mut camera, 1.0);
+    walk(
+      &mut walker,
+      &holding(&[KeyCode::Space]),
+      &world,
+      &mut camera,
+      0.3,
+    );
+    assert!(camera.position().y + Walk::HEIGHT - Walk::EYE_HEIGHT <= 3.0);
+  }
+}
@@ -1,2 +1,7 @@
 pub mod block;
+pub mod clock;
+pub mod editor;
+pub mod hotbar;
+pub mod scene;
+pub mod state;
 pub mod world;
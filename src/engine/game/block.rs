@@ -4,26 +4,37 @@ pub mod registry;
 
 use std::rc::Rc;
 
-use crate::engine::texture::BloomTexture;
+use crate::engine::material::Material;
 
 use self::model::BlockModel;
 
 pub struct Block {
   pub name: String,
   pub model: Rc<BlockModel>,
-  pub texture: Rc<BloomTexture>,
+  pub material: Option<Rc<Material>>, // Only missing from test blocks
 }
 
 impl Block {
   pub fn new(
     name: &str,
     model: &Rc<BlockModel>,
-    texture: &Rc<BloomTexture>,
+    material: &Rc<Material>,
   ) -> Self {
     Self {
       name: name.into(),
       model: Rc::clone(model),
-      texture: Rc::clone(texture),
+      material: Some(Rc::clone(material)),
+    }
+  }
+
+  // Tests have no GPU to create materials with. These blocks are never drawn,
+  // but otherwise behave like any other block, occluding their neighbours
+  #[cfg(test)]
+  pub fn without_material(name: &str, model: &Rc<BlockModel>) -> Self {
+    Self {
+      name: name.into(),
+      model: Rc::clone(model),
+      material: None,
     }
   }
 
@@ -34,4 +45,8 @@ impl Block {
   pub fn model(&self) -> &BlockModel {
     &self.model
   }
+
+  pub fn material(&self) -> Option<&Rc<Material>> {
+    self.material.as_ref()
+  }
 }
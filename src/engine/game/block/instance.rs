@@ -28,7 +28,7 @@ impl BlockInstance {
     Self { block, position }
   }
 
-  pub fn block_type(&self) -> &Block {
+  pub fn block_type(&self) -> &Rc<Block> {
     &self.block
   }
 
@@ -38,6 +38,15 @@ impl BlockInstance {
 }
 
 impl BlockPosition {
+  // Block whose cell the point is in
+  pub fn containing(point: Point3<f32>) -> Self {
+    Self {
+      x: point.x.floor() as i32,
+      y: point.y.floor() as i32,
+      z: point.z.floor() as i32,
+    }
+  }
+
   pub fn north(&self) -> Self {
     Self {
       x: self.x + 1,
@@ -67,10 +67,15 @@ pub struct BlockModel {
 
   inside_indices: Vec<u16>,
 
-  draw_category: usize,
+  // Boxes rays and bodies collide with, relative to the block origin and
+  // within the unit cube. Empty for blocks nothing collides with
+  collision_boxes: Vec<(Point3<f32>, Point3<f32>)>,
 }
 
 impl BlockModel {
+  const FULL_CUBE: (Point3<f32>, Point3<f32>) =
+    (Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+
   pub fn indices_of(
     &self,
     location: BlockMeshLocation,
@@ -119,6 +124,32 @@ impl BlockModel {
     .is_empty()
   }
 
+  // Only models closed by opaque faces on all sides cast ambient occlusion
+  pub fn is_occluder(&self) -> bool {
+    [
+      BlockMeshLocation::North,
+      BlockMeshLocation::South,
+      BlockMeshLocation::East,
+      BlockMeshLocation::West,
+      BlockMeshLocation::Top,
+      BlockMeshLocation::Bottom,
+    ]
+    .into_iter()
+    .all(|location| self.has_face_at(location))
+  }
+
+  pub fn collision_boxes(&self) -> &[(Point3<f32>, Point3<f32>)] {
+    &self.collision_boxes
+  }
+  // For models whose shape differs from what they look like
+  pub fn with_collision_boxes(
+    mut self,
+    boxes: Vec<(Point3<f32>, Point3<f32>)>,
+  ) -> Self {
+    self.collision_boxes = boxes;
+    self
+  }
+
   pub fn vertices_at(&self, origin: Point3<f32>) -> Vec<Vertex> {
     self
       .vertices
@@ -131,10 +162,6 @@ impl BlockModel {
       .collect()
   }
 
-  pub fn draw_category(&self) -> usize {
-    self.draw_category
-  }
-
   pub fn model_simple() -> Self {
     Self {
       vertices: vec![
@@ -185,7 +212,7 @@ impl BlockModel {
 
       inside_indices: vec![],
 
-      draw_category: 0,
+      collision_boxes: vec![Self::FULL_CUBE],
     }
   }
 
@@ -239,7 +266,7 @@ impl BlockModel {
 
       inside_indices: vec![],
 
-      draw_category: 1,
+      collision_boxes: vec![Self::FULL_CUBE],
     }
   }
 
@@ -293,7 +320,7 @@ impl BlockModel {
 
       inside_indices: vec![],
 
-      draw_category: 0,
+      collision_boxes: vec![Self::FULL_CUBE],
     }
   }
 }
@@ -20,4 +20,9 @@ impl BlockRegistry {
   pub fn find_block(&self, name: &str) -> Rc<Block> {
     Rc::clone(self.blocks.get(name).unwrap())
   }
+
+  // In no particular order
+  pub fn blocks(&self) -> impl Iterator<Item = &Rc<Block>> {
+    self.blocks.values()
+  }
 }
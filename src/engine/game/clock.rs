@@ -0,0 +1,230 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Vector3};
+
+// Real seconds for a whole in-game day at a scale of 1.0
+pub const DAY_LENGTH: f32 = 20.0 * 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DayPeriod {
+  Dawn,
+  Noon,
+  Dusk,
+  Midnight,
+}
+
+impl DayPeriod {
+  pub fn hour(&self) -> f32 {
+    match self {
+      DayPeriod::Dawn => 6.0,
+      DayPeriod::Noon => 12.0,
+      DayPeriod::Dusk => 18.0,
+      DayPeriod::Midnight => 0.0,
+    }
+  }
+
+  pub fn next(&self) -> DayPeriod {
+    match self {
+      DayPeriod::Dawn => DayPeriod::Noon,
+      DayPeriod::Noon => DayPeriod::Dusk,
+      DayPeriod::Dusk => DayPeriod::Midnight,
+      DayPeriod::Midnight => DayPeriod::Dawn,
+    }
+  }
+}
+
+// In-game time, advanced from the engine loop. Everything derived from it is
+// a pure function of the time of day, so the same time always looks the same
+pub struct WorldClock {
+  day: u32,
+  time_of_day: f32, // In hours, 0.0 is midnight and 12.0 is noon
+  scale: f32,       // In-game time speed relative to DAY_LENGTH
+  paused: bool,
+}
+
+impl WorldClock {
+  pub fn new() -> Self {
+    Self {
+      day: 0,
+      time_of_day: DayPeriod::Noon.hour(),
+      scale: 1.0,
+      paused: false,
+    }
+  }
+
+  pub fn advance(&mut self, delta: f32) {
+    if self.paused {
+      return;
+    }
+    let hours = self.time_of_day + delta * self.scale * 24.0 / DAY_LENGTH;
+    self.day += hours.div_euclid(24.0) as u32;
+    self.time_of_day = hours.rem_euclid(24.0);
+  }
+
+  pub fn day(&self) -> u32 {
+    self.day
+  }
+  pub fn time_of_day(&self) -> f32 {
+    self.time_of_day
+  }
+  pub fn set_time_of_day(&mut self, hours: f32) {
+    self.time_of_day = hours.rem_euclid(24.0);
+  }
+
+  // Moves forward to the next time the given period starts
+  pub fn skip_to(&mut self, period: DayPeriod) {
+    if period.hour() <= self.time_of_day {
+      self.day += 1;
+    }
+    self.time_of_day = period.hour();
+  }
+
+  // The last period which started
+  pub fn period(&self) -> DayPeriod {
+    match self.time_of_day {
+      hour if hour < DayPeriod::Dawn.hour() => DayPeriod::Midnight,
+      hour if hour < DayPeriod::Noon.hour() => DayPeriod::Dawn,
+      hour if hour < DayPeriod::Dusk.hour() => DayPeriod::Noon,
+      _ => DayPeriod::Dusk,
+    }
+  }
+
+  pub fn scale(&self) -> f32 {
+    self.scale
+  }
+  pub fn set_scale(&mut self, scale: f32) {
+    self.scale = scale.max(0.0);
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+  pub fn set_paused(&mut self, paused: bool) {
+    self.paused = paused;
+  }
+
+  // Points towards the sun, which rises in the east (+z) at dawn, passes
+  // slightly to the south (-x) at noon and sets in the west at dusk
+  pub fn sun_direction(&self) -> Vector3<f32> {
+    let angle = (self.time_of_day - DayPeriod::Dawn.hour()) / 24.0 * 2.0 * PI;
+    let (sin, cos) = angle.sin_cos();
+    Vector3::new(-0.3, sin, cos).normalize()
+  }
+
+  // Fades the sun out while it crosses the horizon
+  pub fn sun_intensity(&self) -> f32 {
+    let height = self.sun_direction().y;
+    ((height + 0.05) / 0.15).clamp(0.0, 1.0)
+  }
+
+  pub fn ambient(&self) -> f32 {
+    0.15 + 0.35 * self.sun_intensity()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const EPSILON: f32 = 1e-4;
+
+  fn clock_at(hours: f32) -> WorldClock {
+    let mut clock = WorldClock::new();
+    clock.set_time_of_day(hours);
+    clock
+  }
+
+  #[test]
+  fn advance_follows_the_scale() {
+    let mut clock = WorldClock::new();
+    clock.advance(DAY_LENGTH / 24.0);
+    assert!((clock.time_of_day() - 13.0).abs() < EPSILON);
+
+    clock.set_scale(2.0);
+    clock.advance(DAY_LENGTH / 24.0);
+    assert!((clock.time_of_day() - 15.0).abs() < EPSILON);
+    assert_eq!(clock.day(), 0);
+  }
+
+  #[test]
+  fn advance_wraps_into_the_next_day() {
+    let mut clock = WorldClock::new();
+    clock.set_scale(2.0);
+    clock.advance(DAY_LENGTH / 4.0);
+    assert_eq!(clock.day(), 1);
+    assert!(clock.time_of_day().abs() < EPSILON);
+  }
+
+  #[test]
+  fn paused_clocks_do_not_advance() {
+    let mut clock = WorldClock::new();
+    clock.set_paused(true);
+    clock.advance(DAY_LENGTH);
+    assert_eq!(clock.day(), 0);
+    assert_eq!(clock.time_of_day(), DayPeriod::Noon.hour());
+
+    clock.set_paused(false);
+    clock.advance(DAY_LENGTH);
+    assert_eq!(clock.day(), 1);
+  }
+
+  #[test]
+  fn skip_to_a_later_period_stays_on_the_same_day() {
+    let mut clock = WorldClock::new();
+    clock.skip_to(DayPeriod::Dusk);
+    assert_eq!(clock.day(), 0);
+    assert_eq!(clock.time_of_day(), DayPeriod::Dusk.hour());
+    assert_eq!(clock.period(), DayPeriod::Dusk);
+  }
+
+  #[test]
+  fn skip_to_an_earlier_period_wraps_into_the_next_day() {
+    let mut clock = WorldClock::new();
+    clock.skip_to(DayPeriod::Dawn);
+    assert_eq!(clock.day(), 1);
+    assert_eq!(clock.time_of_day(), DayPeriod::Dawn.hour());
+
+    // The current period starts again on the next day
+    clock.skip_to(DayPeriod::Dawn);
+    assert_eq!(clock.day(), 2);
+  }
+
+  #[test]
+  fn set_time_of_day_wraps_around_the_day() {
+    let mut clock = WorldClock::new();
+    clock.set_time_of_day(30.0);
+    assert_eq!(clock.time_of_day(), 6.0);
+    clock.set_time_of_day(-2.0);
+    assert_eq!(clock.time_of_day(), 22.0);
+    assert_eq!(clock.day(), 0);
+  }
+
+  #[test]
+  fn noon_is_fully_lit() {
+    let clock = clock_at(DayPeriod::Noon.hour());
+    let sun = clock.sun_direction();
+    assert!((sun.magnitude() - 1.0).abs() < EPSILON);
+    assert!(sun.y > 0.95);
+    assert!(sun.z.abs() < EPSILON);
+    assert_eq!(clock.sun_intensity(), 1.0);
+    assert!((clock.ambient() - 0.5).abs() < EPSILON);
+  }
+
+  #[test]
+  fn midnight_is_unlit() {
+    let clock = clock_at(DayPeriod::Midnight.hour());
+    assert!(clock.sun_direction().y < -0.95);
+    assert_eq!(clock.sun_intensity(), 0.0);
+    assert!((clock.ambient() - 0.15).abs() < EPSILON);
+  }
+
+  #[test]
+  fn dusk_sets_in_the_west() {
+    let clock = clock_at(DayPeriod::Dusk.hour());
+    let sun = clock.sun_direction();
+    assert!(sun.y.abs() < EPSILON);
+    assert!(sun.z < -0.95);
+    assert!((clock.sun_intensity() - 1.0 / 3.0).abs() < EPSILON);
+    assert!((clock.ambient() - (0.15 + 0.35 / 3.0)).abs() < EPSILON);
+  }
+}
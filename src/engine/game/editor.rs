@@ -0,0 +1,115 @@
+use crate::{
+  engine::camera::Camera,
+  input::{action::Action, Input},
+};
+
+use super::{
+  block::instance::BlockPosition,
+  hotbar::Hotbar,
+  world::{history::EditHistory, raycast::RaycastHit, World},
+};
+
+// Places and breaks blocks where the camera looks. Holding an edit button
+// repeats the edit, and everything edited until it is released is a single
+// stroke, undone at once
+pub struct BlockEditor {
+  pub hotbar: Hotbar,
+  pub history: EditHistory,
+  stroke: Option<Action>, // PlaceBlock or BreakBlock, while held
+  repeat: f32,            // Seconds until the held edit repeats
+}
+
+impl BlockEditor {
+  const REACH: f32 = 8.0; // Blocks
+  const REPEAT_DELAY: f32 = 0.2; // Seconds
+
+  pub fn new(hotbar: Hotbar, history: EditHistory) -> Self {
+    Self {
+      hotbar,
+      history,
+      stroke: None,
+      repeat: 0.0,
+    }
+  }
+
+  // Blocks are only edited while aiming with the crosshair. Strokes start
+  // with a press while aiming, so the click grabbing the cursor doesn't edit
+  // too. Returns the block the camera looks at after the edits, if aiming
+  pub fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    aiming: bool,
+    camera: &Camera,
+    world: &mut World,
+  ) -> Option<RaycastHit> {
+    let slots = input.pressed(Action::NextBlock) as i32
+      - input.pressed(Action::PreviousBlock) as i32;
+    self.hotbar.scroll(slots);
+    // Scrolling up goes back towards the first slot
+    if input.scroll() != 0.0 {
+      self.hotbar.scroll(-input.scroll().signum() as i32);
+    }
+
+    if aiming {
+      for action in [Action::BreakBlock, Action::PlaceBlock] {
+        if input.pressed(action) {
+          self.history.commit();
+          self.stroke = Some(action);
+          self.repeat = 0.0;
+        }
+      }
+    }
+    match self.stroke {
+      Some(action) if aiming && input.held(action) => {
+        self.repeat -= delta;
+        if self.repeat <= 0.0 {
+          self.repeat = Self::REPEAT_DELAY;
+          self.edit(action, camera, world);
+        }
+      }
+      Some(_) => {
+        self.stroke = None;
+        self.history.commit();
+      }
+      None => {}
+    }
+
+    if input.pressed(Action::Undo) {
+      self.history.undo(world);
+    }
+    if input.pressed(Action::Redo) {
+      self.history.redo(world);
+    }
+
+    if aiming {
+      Self::target(camera, world)
+    } else {
+      None
+    }
+  }
+
+  fn target(camera: &Camera, world: &World) -> Option<RaycastHit> {
+    world.raycast(camera.position(), camera.view_direction(), Self::REACH)
+  }
+
+  fn edit(&mut self, action: Action, camera: &Camera, world: &mut World) {
+    let Some(hit) = Self::target(camera, world) else {
+      return;
+    };
+    if action == Action::BreakBlock {
+      self.history.set_block(world, hit.position(), None);
+      return;
+    }
+    // Against the targeted face, unless something is in the way
+    let position = hit.adjacent();
+    let free = world.block_at(position).is_none()
+      && position != BlockPosition::containing(camera.position());
+    match self.hotbar.selected() {
+      Some(block) if free => {
+        self.history.set_block(world, position, Some(block))
+      }
+      _ => {}
+    }
+  }
+}
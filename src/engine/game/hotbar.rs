@@ -0,0 +1,41 @@
+use std::rc::Rc;
+
+use super::block::{registry::BlockRegistry, Block};
+
+// Blocks the player can place, one of them selected at a time
+pub struct Hotbar {
+  blocks: Vec<Rc<Block>>,
+  selected: usize,
+}
+
+impl Hotbar {
+  // Every registered block, sorted by name so the slots stay the same
+  // from one run to the next
+  pub fn from_registry(registry: &BlockRegistry) -> Self {
+    let mut blocks: Vec<Rc<Block>> = registry.blocks().cloned().collect();
+    blocks.sort_by(|b1, b2| b1.name().cmp(b2.name()));
+    Self {
+      blocks,
+      selected: 0,
+    }
+  }
+
+  pub fn blocks(&self) -> &[Rc<Block>] {
+    &self.blocks
+  }
+
+  pub fn selected(&self) -> Option<&Rc<Block>> {
+    self.blocks.get(self.selected)
+  }
+  pub fn selected_slot(&self) -> usize {
+    self.selected
+  }
+
+  // Moves the selection by the given number of slots, wrapping around
+  pub fn scroll(&mut self, slots: i32) {
+    if !self.blocks.is_empty() {
+      let count = self.blocks.len() as i32;
+      self.selected = (self.selected as i32 + slots).rem_euclid(count) as usize;
+    }
+  }
+}
@@ -0,0 +1,122 @@
+use super::{block::registry::BlockRegistry, world::World};
+
+// Predefined worlds, the BLOOM_SCENE environment variable picks which one the
+// engine starts with. Besides the default one, they are reference setups to
+// check rendering features against after changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scene {
+  Default,
+  // Glass columns and a glass shell around stone, seen through each other
+  // from most angles. Missing or flickering faces mean transparent geometry
+  // is no longer blended in the right order
+  StackedGlass,
+}
+
+impl Scene {
+  pub fn from_name(name: &str) -> Option<Scene> {
+    match name {
+      "default" => Some(Scene::Default),
+      "stacked_glass" => Some(Scene::StackedGlass),
+      _ => None,
+    }
+  }
+
+  pub fn from_env() -> Scene {
+    std::env::var("BLOOM_SCENE")
+      .ok()
+      .and_then(|name| Scene::from_name(&name))
+      .unwrap_or(Scene::Default)
+  }
+
+  pub fn build(&self, registry: &BlockRegistry) -> World {
+    let mut world = World::new();
+    match self {
+      Scene::Default => {
+        let stone = registry.find_block("stone");
+        let oak_log = registry.find_block("oak_log");
+        world.set_block((1, 1, 1).into(), Some(&stone));
+        world.set_block((1, 0, 1).into(), Some(&oak_log));
+      }
+      Scene::StackedGlass => {
+        let stone = registry.find_block("stone");
+        let glass = registry.find_block("glass");
+
+        for x in 0..10 {
+          for z in 0..10 {
+            world.set_block((x, 0, z).into(), Some(&stone));
+          }
+        }
+        // Columns of glass, one behind the other
+        for y in 1..6 {
+          world.set_block((1, y, 1).into(), Some(&glass));
+          world.set_block((1, y, 3).into(), Some(&glass));
+          world.set_block((3, y, 1).into(), Some(&glass));
+        }
+        // Glass shell around a stone core
+        for x in 5..8 {
+          for y in 1..4 {
+            for z in 5..8 {
+              world.set_block((x, y, z).into(), Some(&glass));
+            }
+          }
+        }
+        world.set_block((6, 2, 6).into(), Some(&stone));
+      }
+    }
+    world
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use crate::engine::game::block::{
+    instance::BlockPosition,
+    model::{BlockMeshLocation, BlockModel},
+    Block,
+  };
+
+  use super::*;
+
+  fn registry() -> BlockRegistry {
+    let model = Rc::new(BlockModel::model_simple());
+    let mut registry = BlockRegistry::new();
+    ["stone", "oak_log", "glass"].iter().for_each(|name| {
+      registry.register_block(&Rc::new(Block::without_material(name, &model)))
+    });
+    registry
+  }
+
+  fn name_at(world: &World, position: BlockPosition) -> Option<&str> {
+    world
+      .block_at(position)
+      .map(|block| block.block_type().name())
+  }
+
+  #[test]
+  fn stacked_glass_hides_stone_behind_glass() {
+    let world = Scene::StackedGlass.build(&registry());
+    let core: BlockPosition = (6, 2, 6).into();
+    assert_eq!(name_at(&world, core), Some("stone"));
+    // Seen from any side, the core is behind a layer of glass
+    [
+      BlockMeshLocation::North,
+      BlockMeshLocation::South,
+      BlockMeshLocation::East,
+      BlockMeshLocation::West,
+      BlockMeshLocation::Top,
+      BlockMeshLocation::Bottom,
+    ]
+    .into_iter()
+    .for_each(|side| {
+      assert_eq!(name_at(&world, core.neighbour(side)), Some("glass"))
+    });
+    // Columns stand one behind the other along x and z
+    for y in 1..6 {
+      for (x, z) in [(1, 1), (1, 3), (3, 1)] {
+        assert_eq!(name_at(&world, (x, y, z).into()), Some("glass"));
+      }
+    }
+  }
+}
@@ -0,0 +1,160 @@
+use crate::{
+  engine::{
+    camera::{
+      path::{CameraPath, CameraPlayback, CameraRecorder},
+      Camera,
+    },
+    controller::{CameraController, ControllerKind, ControllerSettings},
+  },
+  input::{action::Action, replay::SessionEnd, Input},
+};
+
+use super::{
+  editor::BlockEditor,
+  world::{raycast::RaycastHit, World},
+};
+
+// Everything the input changes each frame but the renderer: the camera and
+// its controllers, the world and the block editor. It needs no GPU, so tests
+// replay input through the same update as the engine loop
+pub struct GameState {
+  pub world: World,
+  pub editor: BlockEditor,
+  pub controller_settings: ControllerSettings,
+  pub controller_kind: ControllerKind,
+  pub controller: Box<dyn CameraController>,
+  pub camera_playback: Option<CameraPlayback>,
+  camera_recorder: Option<CameraRecorder>,
+  // Last recorded or played path, played (again) by PlayCameraPath
+  camera_path: Option<CameraPath>,
+  pub cursor_grabbed: bool, // The engine grabs the window cursor to match
+}
+
+impl GameState {
+  pub fn new(
+    world: World,
+    editor: BlockEditor,
+    camera_playback: Option<CameraPlayback>,
+    camera: &Camera,
+  ) -> Self {
+    let controller_kind = ControllerKind::FreeFly;
+    Self {
+      world,
+      editor,
+      controller_settings: ControllerSettings::default(),
+      controller_kind,
+      controller: controller_kind.create(camera),
+      camera_playback,
+      camera_recorder: None,
+      camera_path: None,
+      cursor_grabbed: false,
+    }
+  }
+
+  // Returns the block the editor aims at, for the renderer to outline
+  pub fn update(
+    &mut self,
+    delta: f32,
+    input: &Input,
+    camera: &mut Camera,
+  ) -> Option<RaycastHit> {
+    // Checked before the grab, the editor ignores the click grabbing the
+    // cursor
+    let editing = self.cursor_grabbed;
+    if input.pressed(Action::GrabCursor) {
+      self.cursor_grabbed = true;
+    }
+    if input.pressed(Action::ReleaseCursor) {
+      self.cursor_grabbed = false;
+    }
+    if input.pressed(Action::NextController) {
+      self.controller_kind = self.controller_kind.next();
+      self.controller = self.controller_kind.create(camera);
+    }
+    let look = if self.cursor_grabbed {
+      self.controller_settings.look(input.mouse_motion())
+    } else {
+      (0.0, 0.0).into()
+    };
+
+    if input.pressed(Action::RecordCameraPath) {
+      match self.camera_recorder.take() {
+        Some(recorder) => {
+          let path = recorder.finish();
+          let file = CameraRecorder::file_from_env();
+          if let Err(error) = path.save(&file) {
+            eprintln!("Could not save the camera path: {}", error);
+          }
+          self.camera_path = Some(path);
+        }
+        None => self.camera_recorder = Some(CameraRecorder::new()),
+      }
+    }
+    if input.pressed(Action::PlayCameraPath) {
+      match self.camera_playback.take() {
+        Some(playback) => {
+          self.camera_path = Some(playback.into_path());
+          self.controller = self.controller_kind.create(camera);
+        }
+        None => {
+          self.camera_playback =
+            self.camera_path.take().map(CameraPlayback::new)
+        }
+      }
+    }
+
+    match &mut self.camera_playback {
+      Some(playback) => {
+        if !playback.advance(delta, camera) {
+          self.camera_path =
+            self.camera_playback.take().map(CameraPlayback::into_path);
+          // Controllers like orbit would jump back to where they were
+          self.controller = self.controller_kind.create(camera);
+        }
+      }
+      None => self.controller.update(
+        delta,
+        input,
+        look,
+        &self.controller_settings,
+        &self.world,
+        camera,
+      ),
+    }
+    if let Some(recorder) = &mut self.camera_recorder {
+      recorder.record(delta, camera);
+    }
+
+    if input.pressed(Action::PrintCamera) {
+      println!("Camera: {}", camera);
+    }
+    camera.zoom((input.axis(Action::ZoomIn, Action::ZoomOut) * delta).exp());
+    if input.pressed(Action::NextProjection) {
+      camera.set_projection(camera.projection().next());
+    }
+
+    let target =
+      self
+        .editor
+        .update(delta, input, editing, camera, &mut self.world);
+
+    let clock = self.world.clock_mut();
+    if input.pressed(Action::NextDayPeriod) {
+      clock.skip_to(clock.period().next());
+    }
+    if input.pressed(Action::PauseClock) {
+      clock.set_paused(!clock.is_paused());
+    }
+    clock.advance(delta);
+
+    target
+  }
+
+  // What input recordings end with and replays have to reach
+  pub fn session_end(&self, camera: &Camera) -> SessionEnd {
+    SessionEnd {
+      world_digest: self.world.digest(),
+      camera_position: camera.position(),
+    }
+  }
+}
@@ -1,32 +1,89 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+  cmp::Ordering,
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  rc::Rc,
+};
 
+use cgmath::{MetricSpace, Point3};
 use wgpu::Device;
 
 use crate::engine::{camera::Camera, mesh::Mesh};
 
-use self::chunk::Chunk;
+use self::chunk::{Chunk, CHUNK_DIMEN};
 
-use super::block::{instance::BlockPosition, registry::BlockRegistry, Block};
+use super::{
+  block::{
+    instance::{BlockInstance, BlockPosition},
+    registry::BlockRegistry,
+    Block,
+  },
+  clock::WorldClock,
+};
 
 pub mod chunk;
+pub mod history;
+pub mod raycast;
 
 pub struct World {
   loaded_chunks: HashMap<BlockPosition, Chunk>,
+  clock: WorldClock,
 }
 
 impl World {
   pub fn new() -> Self {
     Self {
       loaded_chunks: HashMap::new(),
+      clock: WorldClock::new(),
     }
   }
 
+  pub fn clock(&self) -> &WorldClock {
+    &self.clock
+  }
+  pub fn clock_mut(&mut self) -> &mut WorldClock {
+    &mut self.clock
+  }
+
+  // Origin of the chunk containing the block, rounded down so negative
+  // positions belong to the chunk below them
+  fn chunk_origin(position: BlockPosition) -> BlockPosition {
+    let dimen = CHUNK_DIMEN as i32;
+    BlockPosition {
+      x: position.x.div_euclid(dimen) * dimen,
+      y: position.y.div_euclid(dimen) * dimen,
+      z: position.z.div_euclid(dimen) * dimen,
+    }
+  }
+
+  // Whether the chunk containing the block is loaded
+  pub fn is_chunk_loaded(&self, position: BlockPosition) -> bool {
+    self
+      .loaded_chunks
+      .contains_key(&Self::chunk_origin(position))
+  }
+
+  // Unloads the chunk containing the block, as long as it has no blocks
+  pub fn unload_empty_chunk(&mut self, position: BlockPosition) {
+    let origin = Self::chunk_origin(position);
+    if self.loaded_chunks.get(&origin).is_some_and(Chunk::is_empty) {
+      self.loaded_chunks.remove(&origin);
+    }
+  }
+
+  pub fn block_at(&self, position: BlockPosition) -> Option<&BlockInstance> {
+    self
+      .loaded_chunks
+      .get(&Self::chunk_origin(position))
+      .and_then(|chunk| chunk.block_at_abs(position))
+  }
+
   pub fn set_block(
     &mut self,
     position: BlockPosition,
     block: Option<&Rc<Block>>,
   ) {
-    let chunk_origin = position / 32;
+    let chunk_origin = Self::chunk_origin(position);
     let relpos = position - chunk_origin;
     let chunk = match self.loaded_chunks.get_mut(&chunk_origin) {
       Some(chunk) => chunk,
@@ -38,24 +95,149 @@ impl World {
       }
     };
     chunk.set_block(relpos, block);
+
+    // Blocks on the borders occlude the faces of the neighbouring chunks
+    for offset in [-1, 1] {
+      for neighbour in [
+        position + (offset, 0, 0).into(),
+        position + (0, offset, 0).into(),
+        position + (0, 0, offset).into(),
+      ] {
+        let neighbour_origin = Self::chunk_origin(neighbour);
+        if neighbour_origin != chunk_origin {
+          if let Some(chunk) = self.loaded_chunks.get_mut(&neighbour_origin) {
+            chunk.mark_dirty();
+          }
+        }
+      }
+    }
   }
 
-  pub fn meshes(
-    &mut self,
-    registry: &BlockRegistry,
-    camera: &Camera,
-    device: &Device,
-  ) -> Vec<&Mesh> {
+  // Hash of every block and its position, independent of the order they
+  // were placed in. Equal worlds have equal digests
+  pub fn digest(&self) -> u64 {
+    self
+      .loaded_chunks
+      .values()
+      .flat_map(Chunk::blocks)
+      .map(|block| {
+        let mut hasher = DefaultHasher::new();
+        block.position().hash(&mut hasher);
+        block.block_type().name().hash(&mut hasher);
+        hasher.finish()
+      })
+      .fold(0, u64::wrapping_add)
+  }
+
+  pub fn chunk_count(&self) -> usize {
+    self.loaded_chunks.len()
+  }
+
+  pub fn chunk_bounds(&self) -> Vec<(Point3<f32>, Point3<f32>)> {
+    self.loaded_chunks.values().map(Chunk::bounds).collect()
+  }
+
+  // Rebuilds the meshes of the chunks changed since the last call
+  pub fn update_meshes(&mut self, registry: &BlockRegistry, device: &Device) {
+    let dirty: Vec<BlockPosition> = self
+      .loaded_chunks
+      .values()
+      .filter(|chunk| chunk.is_dirty())
+      .map(Chunk::origin)
+      .collect();
+    // Taken out of the world while meshed, as meshing reads the blocks of
+    // the neighbouring chunks
+    for origin in dirty {
+      if let Some(mut chunk) = self.loaded_chunks.remove(&origin) {
+        chunk.invalidate_all_meshes(self, registry, device);
+        self.loaded_chunks.insert(origin, chunk);
+      }
+    }
+  }
+
+  pub fn meshes(&self, camera: &Camera) -> Vec<&Mesh> {
     let mut meshes = self
       .loaded_chunks
-      .values_mut()
+      .values()
       .filter(|chunk| chunk.is_visible(camera))
       .fold(Vec::<&Mesh>::new(), |mut acc, chunk| {
-        chunk.invalidate_all_meshes(registry, device);
         acc.extend(chunk.meshes());
         acc
       });
-    meshes.sort_by(|m1, m2| m1.draw_category().cmp(&m2.draw_category()));
+    let eye = camera.position();
+    meshes.sort_by(|m1, m2| draw_order(eye, draw_key(m1), draw_key(m2)));
     meshes
   }
 }
+
+// What the draw order of a mesh depends on: whether it is transparent, its
+// draw category and its center
+type DrawKey = (bool, usize, Point3<f32>);
+
+fn draw_key(mesh: &Mesh) -> DrawKey {
+  (mesh.is_transparent(), mesh.draw_category(), mesh.center())
+}
+
+// Transparent meshes are drawn after the opaque ones, from back to front
+// whatever their category, so they blend over each other correctly. The
+// faces within each transparent mesh are sorted by the renderer
+fn draw_order(eye: Point3<f32>, key1: DrawKey, key2: DrawKey) -> Ordering {
+  match (key1, key2) {
+    ((true, _, center1), (true, _, center2)) => {
+      eye.distance2(center2).total_cmp(&eye.distance2(center1))
+    }
+    ((false, category1, _), (false, category2, _)) => category1.cmp(&category2),
+    ((transparent1, ..), (transparent2, ..)) => transparent1.cmp(&transparent2),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::engine::mesh::{
+    DRAW_CATEGORY_CUTOUT, DRAW_CATEGORY_OPAQUE, DRAW_CATEGORY_TRANSPARENT,
+  };
+
+  use super::*;
+
+  // Centers of chunk meshes along x, the eye past the last one
+  fn chunk_center(chunk: i32) -> Point3<f32> {
+    Point3::new(chunk as f32 * 32.0 + 16.0, 16.0, 16.0)
+  }
+
+  fn sorted(eye: Point3<f32>, mut keys: Vec<DrawKey>) -> Vec<DrawKey> {
+    keys.sort_by(|key1, key2| draw_order(eye, *key1, *key2));
+    keys
+  }
+
+  #[test]
+  fn transparent_meshes_are_drawn_back_to_front() {
+    let eye = Point3::new(100.0, 16.0, 16.0);
+    let glass = |chunk| (true, DRAW_CATEGORY_TRANSPARENT, chunk_center(chunk));
+    let order = sorted(eye, vec![glass(1), glass(-1), glass(2), glass(0)]);
+    assert_eq!(order, vec![glass(-1), glass(0), glass(1), glass(2)]);
+
+    // From the other side, the order is reversed
+    let eye = Point3::new(-100.0, 16.0, 16.0);
+    let order = sorted(eye, vec![glass(1), glass(-1), glass(2), glass(0)]);
+    assert_eq!(order, vec![glass(2), glass(1), glass(0), glass(-1)]);
+  }
+
+  #[test]
+  fn opaque_meshes_are_drawn_first_by_category() {
+    let eye = Point3::new(100.0, 16.0, 16.0);
+    let opaque = (false, DRAW_CATEGORY_OPAQUE, chunk_center(-3));
+    let cutout = (false, DRAW_CATEGORY_CUTOUT, chunk_center(5));
+    let near_glass = (true, DRAW_CATEGORY_TRANSPARENT, chunk_center(2));
+    let far_glass = (true, DRAW_CATEGORY_TRANSPARENT, chunk_center(0));
+    // Transparent meshes of any category blend, after every opaque one
+    let translucent_cutout = (true, DRAW_CATEGORY_CUTOUT, chunk_center(1));
+    let order = sorted(
+      eye,
+      vec![near_glass, cutout, translucent_cutout, far_glass, opaque],
+    );
+    assert_eq!(
+      order,
+      vec![opaque, cutout, far_glass, translucent_cutout, near_glass]
+    );
+  }
+}
@@ -3,6 +3,7 @@ use std::{
   rc::Rc,
 };
 
+use cgmath::{Array, Point3, Vector3};
 use strum::IntoEnumIterator;
 use wgpu::Device;
 
@@ -14,7 +15,9 @@ use crate::engine::{
     registry::BlockRegistry,
     Block,
   },
+  game::world::World,
   mesh::Mesh,
+  model::Vertex,
 };
 
 pub const CHUNK_DIMEN: usize = 32;
@@ -23,20 +26,18 @@ const CHUNK_BLOCK_COUNT: usize = CHUNK_DIMEN * CHUNK_DIMEN * CHUNK_DIMEN;
 // A chunk is a 32x32x32 array of block instances
 pub struct Chunk {
   origin: BlockPosition,
-  blocks: [Option<BlockInstance>; CHUNK_BLOCK_COUNT],
+  blocks: Box<[Option<BlockInstance>]>, // On the heap, too big for the stack
   block_type_counts: HashMap<String, u32>, // Map block_name -> block_count
-  meshes: HashMap<String, Mesh>,           // Map block_name -> mesh
+  meshes: HashMap<String, Mesh>,        // Map block_name -> mesh
 
   dirty: bool, // Set when a block update happens, cleared when all meshes are invalidated
 }
 
 impl Chunk {
-  const EMPTY_BLOCK: Option<BlockInstance> = None;
-
   pub fn new(origin: BlockPosition) -> Self {
     Self {
       origin,
-      blocks: [Self::EMPTY_BLOCK; CHUNK_BLOCK_COUNT],
+      blocks: (0..CHUNK_BLOCK_COUNT).map(|_| None).collect(),
       block_type_counts: HashMap::new(),
       meshes: HashMap::new(),
       dirty: false,
@@ -67,9 +68,28 @@ impl Chunk {
     }
   }
 
-  // pub fn origin(&self) -> BlockPosition {
-  //   self.origin
-  // }
+  // Meshes have to be rebuilt, after an update of the chunk or of a block
+  // next to it in another chunk
+  pub fn is_dirty(&self) -> bool {
+    self.dirty
+  }
+  pub fn mark_dirty(&mut self) {
+    self.dirty = true;
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.block_type_counts.is_empty()
+  }
+
+  pub fn origin(&self) -> BlockPosition {
+    self.origin
+  }
+
+  // Opposite corners of the space covered by the chunk
+  pub fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+    let min: Point3<f32> = self.origin.into();
+    (min, min + Vector3::from_value(CHUNK_DIMEN as f32))
+  }
 
   pub fn set_block(
     &mut self,
@@ -111,6 +131,10 @@ impl Chunk {
       + relpos.z as usize * CHUNK_DIMEN * CHUNK_DIMEN]
       .as_ref()
   }
+  pub fn blocks(&self) -> impl Iterator<Item = &BlockInstance> {
+    self.blocks.iter().flatten()
+  }
+
   pub fn block_at_abs(&self, abspos: BlockPosition) -> Option<&BlockInstance> {
     self.block_at(abspos - self.origin)
   }
@@ -119,12 +143,65 @@ impl Chunk {
     true
   }
 
+  // Blocks past the chunk borders are looked up in the world, so faces at
+  // the borders are occluded by the neighbouring chunks too
+  fn is_occluder_at(&self, world: &World, abspos: BlockPosition) -> bool {
+    match self.block_at_abs(abspos).or_else(|| world.block_at(abspos)) {
+      Some(block) => block.block_type().model().is_occluder(),
+      None => false,
+    }
+  }
+
+  // Sets the ambient occlusion of the vertices used by the given side of the
+  // block at `position`, based on the three blocks touching each corner
+  fn bake_ambient_occlusion(
+    &self,
+    world: &World,
+    position: BlockPosition,
+    side: BlockMeshLocation,
+    side_indices: &[u16],
+    vertices: &mut [Vertex],
+  ) {
+    if side == BlockMeshLocation::Inside {
+      return;
+    }
+    let facing = position.neighbour(side);
+    let normal = facing - position;
+    side_indices.iter().for_each(|index| {
+      let vertex = &mut vertices[*index as usize];
+      let corner = vertex.position() - Vector3::from(position);
+      let corner_direction = |coord: f32| if coord < 0.5 { -1 } else { 1 };
+      let (tangent1, tangent2): (BlockPosition, BlockPosition) =
+        match (normal.x, normal.y) {
+          (0, 0) => (
+            (corner_direction(corner.x), 0, 0).into(),
+            (0, corner_direction(corner.y), 0).into(),
+          ),
+          (0, _) => (
+            (corner_direction(corner.x), 0, 0).into(),
+            (0, 0, corner_direction(corner.z)).into(),
+          ),
+          _ => (
+            (0, corner_direction(corner.y), 0).into(),
+            (0, 0, corner_direction(corner.z)).into(),
+          ),
+        };
+      let level = ao_level(
+        self.is_occluder_at(world, facing + tangent1),
+        self.is_occluder_at(world, facing + tangent2),
+        self.is_occluder_at(world, facing + tangent1 + tangent2),
+      );
+      vertex.set_ao(level as f32 / 3.0);
+    });
+  }
+
   pub fn meshes(&self) -> Values<'_, String, Mesh> {
     self.meshes.values()
   }
 
   pub fn invalidate_all_meshes(
     &mut self,
+    world: &World,
     registry: &BlockRegistry,
     device: &Device,
   ) {
@@ -139,6 +216,9 @@ impl Chunk {
       if self.block_count(block_name) == 0 {
         self.meshes.remove(block_name);
       }
+      let Some(material) = block_type.material() else {
+        return;
+      };
 
       let mut vertices = Vec::new();
       let mut indices = Vec::new();
@@ -149,12 +229,9 @@ impl Chunk {
         .filter_map(|block| block.as_ref())
         .filter(|block| block.block_type().name() == block_name)
         .for_each(|block| {
-          let block_vertices = block
-            .block_type()
-            .model()
-            .vertices_at(block.position().into());
+          let model = block.block_type().model();
+          let mut block_vertices = model.vertices_at(block.position().into());
           let indices_shift = vertices.len() as u16;
-          vertices.extend(block_vertices);
 
           BlockMeshLocation::iter().for_each(|side| {
             let neighbour = self.block_at_abs(block.position().neighbour(side));
@@ -172,21 +249,291 @@ impl Chunk {
               Some(_) => false,
             };
             if include_side {
+              let side_indices = model.indices_of(side, 0);
+              let normal = block.position().neighbour(side) - block.position();
+              side_indices.iter().for_each(|index| {
+                block_vertices[*index as usize].set_normal(normal.into())
+              });
+              self.bake_ambient_occlusion(
+                world,
+                block.position(),
+                side,
+                &side_indices,
+                &mut block_vertices,
+              );
               indices.extend(
-                block.block_type().model().indices_of(side, indices_shift),
+                triangulate_quads(&side_indices, &block_vertices)
+                  .iter()
+                  .map(|index| index + indices_shift),
               );
             }
-          })
+          });
+          vertices.extend(block_vertices);
         });
       let mesh = Mesh::new(
         format!("mesh:chunk({}):{}", self.origin, block_name).as_str(),
         &vertices,
         &indices,
-        Rc::clone(&block_type.texture),
-        block_type.model.draw_category(),
+        Rc::clone(material),
         device,
       );
       self.meshes.insert(String::from(block_name), mesh);
     });
   }
 }
+
+// Ambient occlusion level of a face corner, 0 being the darkest and 3 fully lit
+fn ao_level(side1: bool, side2: bool, corner: bool) -> u8 {
+  if side1 && side2 {
+    0
+  } else {
+    3 - (side1 as u8 + side2 as u8 + corner as u8)
+  }
+}
+
+// Quads come as [a, b, c, a, c, d], split along the a-c diagonal. Splitting
+// along the darker diagonal makes the occlusion gradient anisotropic, so such
+// quads get flipped to use the b-d diagonal instead
+fn triangulate_quads(indices: &[u16], vertices: &[Vertex]) -> Vec<u16> {
+  indices
+    .chunks(6)
+    .flat_map(|quad| match *quad {
+      [a, b, c, a2, c2, d] if a == a2 && c == c2 => {
+        let ao = |index: u16| vertices[index as usize].ao();
+        if ao(a) + ao(c) < ao(b) + ao(d) {
+          vec![b, c, d, b, d, a]
+        } else {
+          quad.to_vec()
+        }
+      }
+      _ => quad.to_vec(),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::engine::game::block::model::BlockModel;
+
+  use super::*;
+
+  type Corner = (i32, i32, i32);
+
+  fn world_with(model: BlockModel, positions: &[Corner]) -> World {
+    let block = Rc::new(Block::without_material("block", &Rc::new(model)));
+    let mut world = World::new();
+    positions
+      .iter()
+      .for_each(|position| world.set_block((*position).into(), Some(&block)));
+    world
+  }
+
+  fn stone_world(positions: &[Corner]) -> World {
+    world_with(BlockModel::model_simple(), positions)
+  }
+
+  // Ambient occlusion level of each corner of a side of the block at
+  // `position`, the corners relative to the block
+  fn face_ao(
+    world: &World,
+    position: Corner,
+    side: BlockMeshLocation,
+  ) -> Vec<(Corner, u32)> {
+    let position: BlockPosition = position.into();
+    let chunk = &world.loaded_chunks[&World::chunk_origin(position)];
+    let model = BlockModel::model_simple();
+    let mut vertices = model.vertices_at(position.into());
+    let indices = model.indices_of(side, 0);
+    chunk.bake_ambient_occlusion(
+      world,
+      position,
+      side,
+      &indices,
+      &mut vertices,
+    );
+    let mut corners: Vec<(Corner, u32)> = indices
+      .iter()
+      .map(|index| {
+        let vertex = &vertices[*index as usize];
+        let corner = vertex.position() - Vector3::from(position);
+        let corner = (corner.x as i32, corner.y as i32, corner.z as i32);
+        (corner, (vertex.ao() * 3.0).round() as u32)
+      })
+      .collect();
+    corners.sort();
+    corners.dedup();
+    corners
+  }
+
+  #[test]
+  fn lone_blocks_are_fully_lit() {
+    let world = stone_world(&[(0, 0, 0)]);
+    BlockMeshLocation::iter()
+      .filter(|side| *side != BlockMeshLocation::Inside)
+      .for_each(|side| {
+        face_ao(&world, (0, 0, 0), side)
+          .iter()
+          .for_each(|(_, level)| assert_eq!(*level, 3));
+      });
+  }
+
+  #[test]
+  fn inner_corner_darkens_the_floor() {
+    // Walls on the south and west sides, above the floor block
+    let world = stone_world(&[(0, 0, 0), (-1, 1, 0), (0, 1, -1)]);
+    assert_eq!(
+      face_ao(&world, (0, 0, 0), BlockMeshLocation::Top),
+      vec![
+        ((0, 1, 0), 0),
+        ((0, 1, 1), 2),
+        ((1, 1, 0), 2),
+        ((1, 1, 1), 3),
+      ]
+    );
+  }
+
+  #[test]
+  fn diagonal_blocks_darken_one_corner() {
+    let world = stone_world(&[(0, 0, 0), (1, 1, 1)]);
+    assert_eq!(
+      face_ao(&world, (0, 0, 0), BlockMeshLocation::Top),
+      vec![
+        ((0, 1, 0), 3),
+        ((0, 1, 1), 3),
+        ((1, 1, 0), 3),
+        ((1, 1, 1), 2),
+      ]
+    );
+  }
+
+  #[test]
+  fn overhang_darkens_the_side_and_top_below() {
+    // Hangs over the north side of the block
+    let world = stone_world(&[(0, 0, 0), (1, 1, 0)]);
+    assert_eq!(
+      face_ao(&world, (0, 0, 0), BlockMeshLocation::North),
+      vec![
+        ((1, 0, 0), 3),
+        ((1, 0, 1), 3),
+        ((1, 1, 0), 2),
+        ((1, 1, 1), 2),
+      ]
+    );
+    assert_eq!(
+      face_ao(&world, (0, 0, 0), BlockMeshLocation::Top),
+      vec![
+        ((0, 1, 0), 3),
+        ((0, 1, 1), 3),
+        ((1, 1, 0), 2),
+        ((1, 1, 1), 2),
+      ]
+    );
+  }
+
+  #[test]
+  fn occluders_in_neighbouring_chunks_darken_the_border() {
+    // (32, 1, 0) is in the next chunk along x and (31, 1, -1) in the one
+    // along -z
+    let world = stone_world(&[(31, 0, 0), (32, 1, 0), (31, 1, -1)]);
+    assert_eq!(world.chunk_count(), 3);
+    assert_eq!(
+      face_ao(&world, (31, 0, 0), BlockMeshLocation::Top),
+      vec![
+        ((0, 1, 0), 2),
+        ((0, 1, 1), 3),
+        ((1, 1, 0), 0),
+        ((1, 1, 1), 2),
+      ]
+    );
+
+    // In the previous chunk along x, diagonal to the east face
+    let world = stone_world(&[(0, 0, 0), (-1, 0, 1)]);
+    assert_eq!(
+      face_ao(&world, (0, 0, 0), BlockMeshLocation::East),
+      vec![
+        ((0, 0, 1), 2),
+        ((0, 1, 1), 2),
+        ((1, 0, 1), 3),
+        ((1, 1, 1), 3),
+      ]
+    );
+  }
+
+  #[test]
+  fn transparent_blocks_do_not_darken() {
+    let mut world = world_with(
+      BlockModel::model_simple_transparent(),
+      &[(-1, 1, 0), (0, 1, -1)],
+    );
+    let stone = Rc::new(Block::without_material(
+      "stone",
+      &Rc::new(BlockModel::model_simple()),
+    ));
+    world.set_block((0, 0, 0).into(), Some(&stone));
+    face_ao(&world, (0, 0, 0), BlockMeshLocation::Top)
+      .iter()
+      .for_each(|(_, level)| assert_eq!(*level, 3));
+  }
+
+  #[test]
+  fn ao_level_counts_occluders() {
+    assert_eq!(ao_level(false, false, false), 3);
+    assert_eq!(ao_level(true, false, false), 2);
+    assert_eq!(ao_level(false, true, false), 2);
+    assert_eq!(ao_level(false, false, true), 2);
+    assert_eq!(ao_level(true, false, true), 1);
+    assert_eq!(ao_level(false, true, true), 1);
+  }
+
+  #[test]
+  fn ao_level_both_sides_hide_the_corner() {
+    assert_eq!(ao_level(true, true, false), 0);
+    assert_eq!(ao_level(true, true, true), 0);
+  }
+
+  fn quad_vertices(ao: [f32; 4]) -> Vec<Vertex> {
+    ao.iter()
+      .map(|ao| {
+        let mut vertex = Vertex::new(0.0, 0.0, 0.0, 0.0, 0.0);
+        vertex.set_ao(*ao);
+        vertex
+      })
+      .collect()
+  }
+
+  #[test]
+  fn quads_keep_the_brighter_diagonal() {
+    let vertices = quad_vertices([1.0, 0.0, 1.0, 0.0]);
+    assert_eq!(
+      triangulate_quads(&[0, 1, 2, 0, 2, 3], &vertices),
+      vec![0, 1, 2, 0, 2, 3]
+    );
+  }
+
+  #[test]
+  fn quads_flip_away_from_the_darker_diagonal() {
+    let vertices = quad_vertices([0.0, 1.0, 0.0, 1.0]);
+    assert_eq!(
+      triangulate_quads(&[0, 1, 2, 0, 2, 3], &vertices),
+      vec![1, 2, 3, 1, 3, 0]
+    );
+  }
+
+  #[test]
+  fn evenly_lit_quads_are_not_flipped() {
+    let vertices = quad_vertices([0.5; 4]);
+    assert_eq!(
+      triangulate_quads(&[0, 1, 2, 0, 2, 3], &vertices),
+      vec![0, 1, 2, 0, 2, 3]
+    );
+  }
+
+  #[test]
+  fn non_quad_triangles_are_kept() {
+    let vertices = quad_vertices([0.0, 1.0, 0.0, 1.0]);
+    assert_eq!(
+      triangulate_quads(&[0, 1, 2, 1, 2, 3], &vertices),
+      vec![0, 1, 2, 1, 2, 3]
+    );
+  }
+}
@@ -0,0 +1,355 @@
+use std::{collections::VecDeque, rc::Rc};
+
+use crate::engine::game::block::{instance::BlockPosition, Block};
+
+use super::World;
+
+// Block at a position before and after an edit
+struct BlockChange {
+  position: BlockPosition,
+  before: Option<Rc<Block>>,
+  after: Option<Rc<Block>>,
+}
+
+// Edits undone and redone together, in the order they were made
+struct Transaction {
+  changes: Vec<BlockChange>,
+  // Positions in the chunks the edits loaded, unloaded again by undoing
+  created_chunks: Vec<BlockPosition>,
+}
+
+impl Transaction {
+  fn undo(&self, world: &mut World) {
+    self.changes.iter().rev().for_each(|change| {
+      world.set_block(change.position, change.before.as_ref());
+    });
+    self
+      .created_chunks
+      .iter()
+      .for_each(|position| world.unload_empty_chunk(*position));
+  }
+
+  fn redo(&self, world: &mut World) {
+    self.changes.iter().for_each(|change| {
+      world.set_block(change.position, change.after.as_ref());
+    });
+  }
+}
+
+// Records the blocks set through it so they can be undone and redone. Edits
+// go into the open transaction until it is committed, so a whole brush
+// stroke is undone at once. Only the last `limit` transactions are kept
+pub struct EditHistory {
+  undo: VecDeque<Transaction>,
+  redo: Vec<Transaction>,
+  open: Option<Transaction>,
+  limit: usize,
+}
+
+impl EditHistory {
+  const DEFAULT_LIMIT: usize = 100;
+
+  pub fn new(limit: usize) -> Self {
+    Self {
+      undo: VecDeque::new(),
+      redo: vec![],
+      open: None,
+      limit,
+    }
+  }
+
+  // Keeps as many transactions as the BLOOM_UNDO_LIMIT environment
+  // variable says, 100 by default
+  pub fn from_env() -> Self {
+    let limit = std::env::var("BLOOM_UNDO_LIMIT")
+      .ok()
+      .and_then(|limit| limit.parse().ok())
+      .unwrap_or(Self::DEFAULT_LIMIT);
+    Self::new(limit)
+  }
+
+  // Opens a transaction if none is, setting a block to what it already is
+  // isn't recorded
+  pub fn set_block(
+    &mut self,
+    world: &mut World,
+    position: BlockPosition,
+    block: Option<&Rc<Block>>,
+  ) {
+    let before = world
+      .block_at(position)
+      .map(|instance| instance.block_type());
+    let unchanged = match (before, block) {
+      (Some(before), Some(after)) => Rc::ptr_eq(before, after),
+      (before, after) => before.is_none() && after.is_none(),
+    };
+    if unchanged {
+      return;
+    }
+    let change = BlockChange {
+      position,
+      before: before.cloned(),
+      after: block.cloned(),
+    };
+    let created_chunk = !world.is_chunk_loaded(position);
+    world.set_block(position, block);
+
+    let transaction = self.open.get_or_insert_with(|| Transaction {
+      changes: vec![],
+      created_chunks: vec![],
+    });
+    transaction.changes.push(change);
+    if created_chunk {
+      transaction.created_chunks.push(position);
+    }
+  }
+
+  // Closes the open transaction, making it the next one to undo. New edits
+  // can't be redone over, so they forget the undone transactions
+  pub fn commit(&mut self) {
+    let Some(transaction) = self.open.take() else {
+      return;
+    };
+    self.redo.clear();
+    self.undo.push_back(transaction);
+    while self.undo.len() > self.limit {
+      self.undo.pop_front();
+    }
+  }
+
+  // False when there was nothing to undo
+  pub fn undo(&mut self, world: &mut World) -> bool {
+    self.commit();
+    match self.undo.pop_back() {
+      Some(transaction) => {
+        transaction.undo(world);
+        self.redo.push(transaction);
+        true
+      }
+      None => false,
+    }
+  }
+
+  // False when there was nothing to redo
+  pub fn redo(&mut self, world: &mut World) -> bool {
+    self.commit();
+    match self.redo.pop() {
+      Some(transaction) => {
+        transaction.redo(world);
+        self.undo.push_back(transaction);
+        true
+      }
+      None => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::engine::game::block::model::BlockModel;
+
+  use super::*;
+
+  struct Blocks {
+    stone: Rc<Block>,
+    glass: Rc<Block>,
+  }
+
+  fn blocks() -> Blocks {
+    let model = Rc::new(BlockModel::model_simple());
+    Blocks {
+      stone: Rc::new(Block::without_material("stone", &model)),
+      glass: Rc::new(Block::without_material("glass", &model)),
+    }
+  }
+
+  // Everything undoing has to restore
+  #[derive(Debug, PartialEq)]
+  struct Snapshot {
+    digest: u64,
+    chunk_count: usize,
+    blocks: Vec<Option<String>>,
+  }
+
+  const PROBES: [(i32, i32, i32); 6] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (2, 3, 4),
+    (31, 0, 0),
+    (32, 0, 0),
+    (-5, 70, -100),
+  ];
+
+  fn snapshot(world: &World) -> Snapshot {
+    Snapshot {
+      digest: world.digest(),
+      chunk_count: world.chunk_count(),
+      blocks: PROBES
+        .iter()
+        .map(|position| {
+          world
+            .block_at((*position).into())
+            .map(|instance| instance.block_type().name().to_string())
+        })
+        .collect(),
+    }
+  }
+
+  fn starting_world(blocks: &Blocks) -> World {
+    let mut world = World::new();
+    world.set_block((0, 0, 0).into(), Some(&blocks.stone));
+    world.set_block((1, 0, 0).into(), Some(&blocks.stone));
+    world
+  }
+
+  // Replaces, removes and adds blocks, in the loaded chunk and in new ones
+  fn edit(history: &mut EditHistory, world: &mut World, blocks: &Blocks) {
+    history.set_block(world, (0, 0, 0).into(), Some(&blocks.glass));
+    history.set_block(world, (1, 0, 0).into(), None);
+    history.set_block(world, (2, 3, 4).into(), Some(&blocks.stone));
+    history.set_block(world, (32, 0, 0).into(), Some(&blocks.glass));
+    history.set_block(world, (-5, 70, -100).into(), Some(&blocks.stone));
+  }
+
+  #[test]
+  fn undo_restores_the_exact_prior_state() {
+    let blocks = blocks();
+    let mut world = starting_world(&blocks);
+    let before = snapshot(&world);
+
+    let mut history = EditHistory::new(10);
+    edit(&mut history, &mut world, &blocks);
+    history.commit();
+    assert_ne!(snapshot(&world), before);
+
+    assert!(history.undo(&mut world));
+    assert_eq!(snapshot(&world), before);
+  }
+
+  #[test]
+  fn redo_restores_the_exact_edited_state() {
+    let blocks = blocks();
+    let mut world = starting_world(&blocks);
+    let mut history = EditHistory::new(10);
+    edit(&mut history, &mut world, &blocks);
+    history.commit();
+    let after = snapshot(&world);
+
+    history.undo(&mut world);
+    assert!(history.redo(&mut world));
+    assert_eq!(snapshot(&world), after);
+
+    // And back again
+    history.undo(&mut world);
+    assert_eq!(snapshot(&world), snapshot(&starting_world(&blocks)));
+  }
+
+  #[test]
+  fn undo_unloads_the_chunks_edits_loaded() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(10);
+    history.set_block(&mut world, (40, 0, 0).into(), Some(&blocks.stone));
+    history.set_block(&mut world, (41, 0, 0).into(), Some(&blocks.stone));
+    history.commit();
+    assert_eq!(world.chunk_count(), 1);
+
+    history.undo(&mut world);
+    assert_eq!(world.chunk_count(), 0);
+    history.redo(&mut world);
+    assert_eq!(world.chunk_count(), 1);
+  }
+
+  #[test]
+  fn undo_keeps_chunks_earlier_edits_loaded() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(10);
+    history.set_block(&mut world, (40, 0, 0).into(), Some(&blocks.stone));
+    history.commit();
+    history.set_block(&mut world, (41, 0, 0).into(), Some(&blocks.stone));
+    history.commit();
+
+    history.undo(&mut world);
+    assert_eq!(world.chunk_count(), 1);
+    assert!(world.block_at((40, 0, 0).into()).is_some());
+    history.undo(&mut world);
+    assert_eq!(world.chunk_count(), 0);
+  }
+
+  #[test]
+  fn transactions_are_undone_one_at_a_time() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(10);
+    let empty = snapshot(&world);
+
+    history.set_block(&mut world, (0, 0, 0).into(), Some(&blocks.stone));
+    history.set_block(&mut world, (1, 0, 0).into(), Some(&blocks.stone));
+    history.commit();
+    let first = snapshot(&world);
+    history.set_block(&mut world, (0, 0, 0).into(), Some(&blocks.glass));
+    history.commit();
+
+    history.undo(&mut world);
+    assert_eq!(snapshot(&world), first);
+    history.undo(&mut world);
+    assert_eq!(snapshot(&world), empty);
+    assert!(!history.undo(&mut world));
+  }
+
+  #[test]
+  fn undo_commits_the_open_transaction() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(10);
+    history.set_block(&mut world, (0, 0, 0).into(), Some(&blocks.stone));
+
+    assert!(history.undo(&mut world));
+    assert!(world.block_at((0, 0, 0).into()).is_none());
+  }
+
+  #[test]
+  fn unchanged_blocks_are_not_recorded() {
+    let blocks = blocks();
+    let mut world = starting_world(&blocks);
+    let mut history = EditHistory::new(10);
+    history.set_block(&mut world, (0, 0, 0).into(), Some(&blocks.stone));
+    history.set_block(&mut world, (5, 5, 5).into(), None);
+    history.commit();
+    assert!(!history.undo(&mut world));
+  }
+
+  #[test]
+  fn new_edits_forget_undone_transactions() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(10);
+    history.set_block(&mut world, (0, 0, 0).into(), Some(&blocks.stone));
+    history.commit();
+    history.undo(&mut world);
+
+    history.set_block(&mut world, (1, 0, 0).into(), Some(&blocks.glass));
+    history.commit();
+    assert!(!history.redo(&mut world));
+    assert!(world.block_at((0, 0, 0).into()).is_none());
+  }
+
+  #[test]
+  fn history_is_bounded() {
+    let blocks = blocks();
+    let mut world = World::new();
+    let mut history = EditHistory::new(2);
+    for x in 0..3 {
+      history.set_block(&mut world, (x, 0, 0).into(), Some(&blocks.stone));
+      history.commit();
+    }
+
+    assert!(history.undo(&mut world));
+    assert!(history.undo(&mut world));
+    assert!(!history.undo(&mut world));
+    // The oldest edit can't be undone anymore
+    assert!(world.block_at((0, 0, 0).into()).is_some());
+    assert!(world.block_at((1, 0, 0).into()).is_none());
+  }
+}
@@ -0,0 +1,443 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::engine::game::block::{
+  instance::{BlockInstance, BlockPosition},
+  model::BlockMeshLocation,
+};
+
+use super::World;
+
+pub struct RaycastHit {
+  pub block: BlockInstance,
+  pub point: Point3<f32>, // Where the ray enters the collision box
+  pub face: BlockMeshLocation, // Side of the block the ray enters through
+  pub distance: f32,
+}
+
+impl RaycastHit {
+  pub fn position(&self) -> BlockPosition {
+    self.block.position()
+  }
+
+  // Cell in front of the hit face, where blocks placed against it go
+  pub fn adjacent(&self) -> BlockPosition {
+    self.position().neighbour(self.face)
+  }
+}
+
+// Sides of a block, along x, y and z, rays going in the positive direction
+// enter through, then rays going in the negative direction
+const ENTRY_FACES: [(BlockMeshLocation, BlockMeshLocation); 3] = [
+  (BlockMeshLocation::South, BlockMeshLocation::North),
+  (BlockMeshLocation::Bottom, BlockMeshLocation::Top),
+  (BlockMeshLocation::West, BlockMeshLocation::East),
+];
+
+impl World {
+  // Closest block whose collision boxes the ray goes through, at most
+  // `max_distance` away from the origin. Visits the cells along the ray in
+  // order (Amanatides and Woo's traversal), so the cost grows with the
+  // distance and not with the size of the world. Rays that are not finite
+  // would never leave the loop, they hit nothing
+  pub fn raycast(
+    &self,
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+  ) -> Option<RaycastHit> {
+    let length = direction.magnitude2();
+    let finite = length.is_finite()
+      && origin.x.is_finite()
+      && origin.y.is_finite()
+      && origin.z.is_finite()
+      && max_distance.is_finite();
+    if !finite || length == 0.0 || max_distance < 0.0 {
+      return None;
+    }
+    let direction = direction.normalize();
+
+    let mut cell = origin.map(|coord| coord.floor() as i32);
+    let mut step = Vector3::new(0, 0, 0);
+    // Distance along the ray to the next cell boundary on each axis, and
+    // between two boundaries of the same axis
+    let mut boundary =
+      Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut spacing = boundary;
+    for axis in 0..3 {
+      if direction[axis] > 0.0 {
+        step[axis] = 1;
+        boundary[axis] =
+          ((cell[axis] + 1) as f32 - origin[axis]) / direction[axis];
+        spacing[axis] = 1.0 / direction[axis];
+      } else if direction[axis] < 0.0 {
+        step[axis] = -1;
+        boundary[axis] = (cell[axis] as f32 - origin[axis]) / direction[axis];
+        spacing[axis] = -1.0 / direction[axis];
+      }
+    }
+
+    let mut distance = 0.0;
+    while distance <= max_distance {
+      let position = BlockPosition {
+        x: cell.x,
+        y: cell.y,
+        z: cell.z,
+      };
+      if let Some(hit) = self
+        .block_at(position)
+        .and_then(|block| intersect_block(block, origin, direction))
+        .filter(|hit| hit.distance <= max_distance)
+      {
+        return Some(hit);
+      }
+
+      let axis = if boundary.x < boundary.y {
+        if boundary.x < boundary.z {
+          0
+        } else {
+          2
+        }
+      } else if boundary.y < boundary.z {
+        1
+      } else {
+        2
+      };
+      distance = boundary[axis];
+      cell[axis] += step[axis];
+      boundary[axis] += spacing[axis];
+    }
+    None
+  }
+}
+
+// Closest collision box of the block the ray goes through. Collision boxes
+// stay within their cell, so no block further along the ray can be closer
+fn intersect_block(
+  block: &BlockInstance,
+  origin: Point3<f32>,
+  direction: Vector3<f32>,
+) -> Option<RaycastHit> {
+  let cell: Point3<f32> = block.position().into();
+  block
+    .block_type()
+    .model()
+    .collision_boxes()
+    .iter()
+    .filter_map(|(min, max)| {
+      intersect_box(cell + min.to_vec(), cell + max.to_vec(), origin, direction)
+    })
+    .min_by(|(d1, _), (d2, _)| d1.total_cmp(d2))
+    .map(|(distance, face)| RaycastHit {
+      block: block.clone(),
+      point: origin + direction * distance,
+      face,
+      distance,
+    })
+}
+
+// Distance along the ray to where it enters the box, and the side it enters
+// through. Rays starting inside the box hit it right away, on the side they
+// would have come in through
+fn intersect_box(
+  min: Point3<f32>,
+  max: Point3<f32>,
+  origin: Point3<f32>,
+  direction: Vector3<f32>,
+) -> Option<(f32, BlockMeshLocation)> {
+  let mut near = f32::NEG_INFINITY;
+  let mut far = f32::INFINITY;
+  let mut face = BlockMeshLocation::Inside;
+  for axis in 0..3 {
+    if direction[axis] == 0.0 {
+      if origin[axis] < min[axis] || origin[axis] > max[axis] {
+        return None;
+      }
+      continue;
+    }
+    let to_min = (min[axis] - origin[axis]) / direction[axis];
+    let to_max = (max[axis] - origin[axis]) / direction[axis];
+    let (entry, exit, entry_face) = if direction[axis] > 0.0 {
+      (to_min, to_max, ENTRY_FACES[axis].0)
+    } else {
+      (to_max, to_min, ENTRY_FACES[axis].1)
+    };
+    if entry > near {
+      near = entry;
+      face = entry_face;
+    }
+    far = far.min(exit);
+  }
+  if near > far || far < 0.0 {
+    return None;
+  }
+  Some((near.max(0.0), face))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use crate::engine::game::block::{model::BlockModel, Block};
+
+  use super::*;
+
+  const EPSILON: f32 = 1e-4;
+
+  fn cube() -> Rc<Block> {
+    Rc::new(Block::without_material(
+      "cube",
+      &Rc::new(BlockModel::model_simple()),
+    ))
+  }
+
+  // Bottom half of the cell
+  fn slab() -> Rc<Block> {
+    let model = BlockModel::model_simple().with_collision_boxes(vec![(
+      Point3::new(0.0, 0.0, 0.0),
+      Point3::new(1.0, 0.5, 1.0),
+    )]);
+    Rc::new(Block::without_material("slab", &Rc::new(model)))
+  }
+
+  type Placement<'a> = ((i32, i32, i32), &'a Rc<Block>);
+
+  fn world_with(blocks: &[Placement]) -> World {
+    let mut world = World::new();
+    blocks.iter().for_each(|(position, block)| {
+      world.set_block((*position).into(), Some(block))
+    });
+    world
+  }
+
+  fn assert_hit(
+    hit: Option<RaycastHit>,
+    position: (i32, i32, i32),
+    face: BlockMeshLocation,
+    distance: f32,
+  ) -> RaycastHit {
+    let hit = hit.expect("the ray should hit a block");
+    assert!(hit.position() == position.into());
+    assert_eq!(hit.face, face);
+    assert!(
+      (hit.distance - distance).abs() < EPSILON,
+      "hit at {} instead of {}",
+      hit.distance,
+      distance
+    );
+    hit
+  }
+
+  #[test]
+  fn ray_along_an_axis() {
+    let cube = cube();
+    let world = world_with(&[((5, 0, 0), &cube)]);
+    let hit = assert_hit(
+      world.raycast(Point3::new(0.5, 0.5, 0.5), Vector3::unit_x(), 10.0),
+      (5, 0, 0),
+      BlockMeshLocation::South,
+      4.5,
+    );
+    assert!((hit.point - Point3::new(5.0, 0.5, 0.5)).magnitude() < EPSILON);
+  }
+
+  #[test]
+  fn ray_direction_is_normalized() {
+    let cube = cube();
+    let world = world_with(&[((0, 3, 0), &cube)]);
+    assert_hit(
+      world.raycast(
+        Point3::new(0.5, 0.5, 0.5),
+        Vector3::new(0.0, 10.0, 0.0),
+        10.0,
+      ),
+      (0, 3, 0),
+      BlockMeshLocation::Bottom,
+      2.5,
+    );
+  }
+
+  #[test]
+  fn diagonal_ray() {
+    let cube = cube();
+    let world = world_with(&[((3, 3, 3), &cube)]);
+    let hit = assert_hit(
+      world.raycast(
+        Point3::new(0.5, 0.5, 0.5),
+        Vector3::new(1.0, 1.0, 1.0),
+        10.0,
+      ),
+      (3, 3, 3),
+      // Enters through the corner, on the first axis checked
+      BlockMeshLocation::South,
+      2.5 * 3f32.sqrt(),
+    );
+    assert!((hit.point - Point3::new(3.0, 3.0, 3.0)).magnitude() < EPSILON);
+  }
+
+  #[test]
+  fn ray_in_the_negative_direction() {
+    let cube = cube();
+    let world = world_with(&[((-3, -2, 0), &cube)]);
+    let hit = assert_hit(
+      world.raycast(
+        Point3::new(0.5, 0.5, 0.5),
+        Vector3::new(-3.0, -2.0, 0.0),
+        10.0,
+      ),
+      (-3, -2, 0),
+      BlockMeshLocation::North,
+      2.5 * 13f32.sqrt() / 3.0,
+    );
+    assert!((hit.point.x - -2.0).abs() < EPSILON);
+  }
+
+  #[test]
+  fn ray_along_negative_z() {
+    let cube = cube();
+    let world = world_with(&[((0, 0, -40), &cube)]);
+    assert_hit(
+      world.raycast(Point3::new(0.5, 0.5, 0.5), -Vector3::unit_z(), 50.0),
+      (0, 0, -40),
+      BlockMeshLocation::East,
+      39.5,
+    );
+  }
+
+  #[test]
+  fn origin_inside_a_block() {
+    let cube = cube();
+    let world = world_with(&[((5, 0, 0), &cube), ((7, 0, 0), &cube)]);
+    let hit = assert_hit(
+      world.raycast(Point3::new(5.5, 0.5, 0.5), Vector3::unit_x(), 10.0),
+      (5, 0, 0),
+      BlockMeshLocation::South,
+      0.0,
+    );
+    assert!((hit.point - Point3::new(5.5, 0.5, 0.5)).magnitude() < EPSILON);
+  }
+
+  #[test]
+  fn max_distance_cuts_the_ray() {
+    let cube = cube();
+    let world = world_with(&[((5, 0, 0), &cube)]);
+    let origin = Point3::new(0.5, 0.5, 0.5);
+    assert!(world.raycast(origin, Vector3::unit_x(), 4.0).is_none());
+    assert!(world.raycast(origin, Vector3::unit_x(), 4.49).is_none());
+    assert!(world.raycast(origin, Vector3::unit_x(), 4.5).is_some());
+  }
+
+  #[test]
+  fn degenerate_rays_hit_nothing() {
+    let cube = cube();
+    let world = world_with(&[((0, 0, 0), &cube)]);
+    let origin = Point3::new(0.5, 0.5, 0.5);
+    assert!(world
+      .raycast(origin, Vector3::new(0.0, 0.0, 0.0), 10.0)
+      .is_none());
+    assert!(world.raycast(origin, Vector3::unit_x(), -1.0).is_none());
+    assert!(world.raycast(origin, Vector3::unit_x(), f32::NAN).is_none());
+  }
+
+  #[test]
+  fn infinite_distance_hits_nothing() {
+    let cube = cube();
+    let world = world_with(&[((3, 0, 0), &cube)]);
+    let origin = Point3::new(0.5, 0.5, 0.5);
+    assert!(world
+      .raycast(origin, Vector3::unit_x(), f32::INFINITY)
+      .is_none());
+    assert!(World::new()
+      .raycast(origin, Vector3::unit_x(), f32::INFINITY)
+      .is_none());
+  }
+
+  #[test]
+  fn nan_rays_hit_nothing() {
+    let cube = cube();
+    // The origin is inside the block, any valid ray would hit it
+    let world = world_with(&[((0, 0, 0), &cube)]);
+    let origin = Point3::new(0.5, 0.5, 0.5);
+    let nan = Vector3::new(f32::NAN, 0.0, 0.0);
+    assert!(world.raycast(origin, nan, 10.0).is_none());
+    assert!(world
+      .raycast(Point3::new(f32::NAN, 0.5, 0.5), Vector3::unit_x(), 10.0)
+      .is_none());
+  }
+
+  #[test]
+  fn empty_world_has_no_hit() {
+    let world = World::new();
+    let hit =
+      world.raycast(Point3::new(0.5, 0.5, 0.5), Vector3::unit_y(), 100.0);
+    assert!(hit.is_none());
+  }
+
+  #[test]
+  fn closest_block_is_hit() {
+    let cube = cube();
+    let world = world_with(&[((8, 0, 0), &cube), ((3, 0, 0), &cube)]);
+    assert_hit(
+      world.raycast(Point3::new(0.5, 0.5, 0.5), Vector3::unit_x(), 10.0),
+      (3, 0, 0),
+      BlockMeshLocation::South,
+      2.5,
+    );
+  }
+
+  #[test]
+  fn hit_face_gives_the_placement_cell() {
+    let cube = cube();
+    let world = world_with(&[((0, 0, 0), &cube)]);
+    let cases = [
+      (Vector3::unit_x(), BlockMeshLocation::South, (-1, 0, 0)),
+      (-Vector3::unit_x(), BlockMeshLocation::North, (1, 0, 0)),
+      (Vector3::unit_y(), BlockMeshLocation::Bottom, (0, -1, 0)),
+      (-Vector3::unit_y(), BlockMeshLocation::Top, (0, 1, 0)),
+      (Vector3::unit_z(), BlockMeshLocation::West, (0, 0, -1)),
+      (-Vector3::unit_z(), BlockMeshLocation::East, (0, 0, 1)),
+    ];
+    for (direction, face, adjacent) in cases {
+      let origin = Point3::new(0.5, 0.5, 0.5) - direction * 3.0;
+      let hit = assert_hit(
+        world.raycast(origin, direction, 10.0),
+        (0, 0, 0),
+        face,
+        2.5,
+      );
+      assert!(hit.adjacent() == adjacent.into());
+      // The ray went through the placement cell just before the hit
+      assert!(
+        BlockPosition::containing(hit.point - direction * 0.01)
+          == adjacent.into()
+      );
+    }
+  }
+
+  #[test]
+  fn partial_collision_box() {
+    let slab = slab();
+    let world = world_with(&[((0, 0, 4), &slab)]);
+    // Above the slab, the ray goes through its cell without hitting it
+    let above =
+      world.raycast(Point3::new(0.5, 0.75, 0.5), Vector3::unit_z(), 10.0);
+    assert!(above.is_none());
+
+    let side = assert_hit(
+      world.raycast(Point3::new(0.5, 0.25, 0.5), Vector3::unit_z(), 10.0),
+      (0, 0, 4),
+      BlockMeshLocation::West,
+      3.5,
+    );
+    assert!((side.point - Point3::new(0.5, 0.25, 4.0)).magnitude() < EPSILON);
+
+    // From above, the ray stops on the top of the slab, not of the cell
+    let top = assert_hit(
+      world.raycast(Point3::new(0.5, 2.0, 4.5), -Vector3::unit_y(), 10.0),
+      (0, 0, 4),
+      BlockMeshLocation::Top,
+      1.5,
+    );
+    assert!((top.point.y - 0.5).abs() < EPSILON);
+    assert!(top.adjacent() == (0, 1, 4).into());
+  }
+}
@@ -0,0 +1,198 @@
+use wgpu::{
+  util::{BufferInitDescriptor, DeviceExt},
+  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+  BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource,
+  BindingType, BufferBindingType, BufferUsages, Device, Face,
+  SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+use super::{
+  mesh::{
+    DRAW_CATEGORY_CUTOUT, DRAW_CATEGORY_OPAQUE, DRAW_CATEGORY_TRANSPARENT,
+  },
+  texture::BloomTexture,
+};
+
+// How the colour of a fragment is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialShader {
+  // Lit by the sun and the ambient light
+  Lit,
+  // Glows with its own light, regardless of the lighting and shadows
+  Emissive,
+}
+
+// How fragments are combined with what is already drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+  Opaque,
+  // Texels under the alpha cutoff are discarded, the others are opaque
+  Cutout,
+  Translucent,
+  // Adds its colour to what is behind, for glowing effects
+  Additive,
+}
+
+// Everything about a material but its texture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialDesc {
+  pub shader: MaterialShader,
+  pub blend: BlendMode,
+  pub depth_write: bool,
+  pub cull_mode: Option<Face>,
+  pub alpha_cutoff: f32, // Only used by cutout materials
+  pub emission: f32,     // Brightness of emissive materials
+}
+
+impl MaterialDesc {
+  pub const OPAQUE: MaterialDesc = MaterialDesc {
+    shader: MaterialShader::Lit,
+    blend: BlendMode::Opaque,
+    depth_write: true,
+    cull_mode: Some(Face::Back),
+    alpha_cutoff: 0.5,
+    emission: 1.0,
+  };
+  // Foliage, seen from both sides through the gaps of its texture
+  pub const CUTOUT: MaterialDesc = MaterialDesc {
+    blend: BlendMode::Cutout,
+    cull_mode: None,
+    ..Self::OPAQUE
+  };
+  // Transparent faces are sorted back to front instead of depth tested
+  // against each other
+  pub const TRANSLUCENT: MaterialDesc = MaterialDesc {
+    blend: BlendMode::Translucent,
+    depth_write: false,
+    ..Self::OPAQUE
+  };
+  // Bright enough to spill into the bloom
+  pub const EMISSIVE: MaterialDesc = MaterialDesc {
+    shader: MaterialShader::Emissive,
+    emission: 4.0,
+    ..Self::OPAQUE
+  };
+
+  // Materials with the same key share a pipeline
+  pub fn key(&self) -> MaterialKey {
+    MaterialKey {
+      shader: self.shader,
+      blend: self.blend,
+      depth_write: self.depth_write,
+      cull_mode: self.cull_mode,
+    }
+  }
+
+  pub fn draw_category(&self) -> usize {
+    match self.blend {
+      BlendMode::Opaque => DRAW_CATEGORY_OPAQUE,
+      BlendMode::Cutout => DRAW_CATEGORY_CUTOUT,
+      BlendMode::Translucent | BlendMode::Additive => DRAW_CATEGORY_TRANSPARENT,
+    }
+  }
+}
+
+// The part of a material baked into its pipeline, the rest is read by the
+// shaders from the material uniform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialKey {
+  pub shader: MaterialShader,
+  pub blend: BlendMode,
+  pub depth_write: bool,
+  pub cull_mode: Option<Face>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+  alpha_cutoff: f32,
+  emission: f32,
+  _padding: [f32; 2],
+}
+
+// A texture and the way it is drawn. Bound as a whole to the block pipelines
+#[derive(Debug)]
+pub struct Material {
+  desc: MaterialDesc,
+  pub bind_group: BindGroup,
+}
+
+impl Material {
+  pub fn new(
+    label: &str,
+    desc: MaterialDesc,
+    texture: &BloomTexture,
+    layout: &BindGroupLayout,
+    device: &Device,
+  ) -> Self {
+    let uniform = MaterialUniform {
+      alpha_cutoff: desc.alpha_cutoff,
+      emission: desc.emission,
+      _padding: [0.0; 2],
+    };
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+      label: Some(label),
+      contents: bytemuck::bytes_of(&uniform),
+      usage: BufferUsages::UNIFORM,
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some(label),
+      layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: BindingResource::TextureView(&texture.view),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: BindingResource::Sampler(&texture.sampler),
+        },
+        BindGroupEntry {
+          binding: 2,
+          resource: buffer.as_entire_binding(),
+        },
+      ],
+    });
+
+    Self { desc, bind_group }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 3] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Sampler(SamplerBindingType::Filtering),
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 2,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Buffer {
+        ty: BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    },
+  ];
+  pub fn bind_group_layout() -> BindGroupLayoutDescriptor<'static> {
+    BindGroupLayoutDescriptor {
+      label: Some("material_bind_group_layout"),
+      entries: &Self::BIND_LAYOUT_ENTRIES,
+    }
+  }
+
+  pub fn desc(&self) -> &MaterialDesc {
+    &self.desc
+  }
+}
@@ -1,6 +1,8 @@
 use std::ops::{AddAssign, SubAssign};
 
-use cgmath::{Deg, Rad, Vector3};
+use cgmath::{
+  Angle, Deg, InnerSpace, Matrix3, Point3, Quaternion, Rad, Rotation3, Vector3,
+};
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -10,6 +12,15 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
   0.0, 0.0, 0.0, 1.0,
 );
 
+// Flips wgpu depth values, so the near plane ends up at 1 and the far one at 0
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+  1.0, 0.0, 0.0, 0.0,
+  0.0, 1.0, 0.0, 0.0,
+  0.0, 0.0, -1.0, 0.0,
+  0.0, 0.0, 1.0, 1.0,
+);
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Orientation2 {
   pub pitch: Deg<f32>, // Rotation around x axis
@@ -61,3 +72,118 @@ impl SubAssign for Orientation2 {
     self.pitch %= Deg(360.0);
   }
 }
+
+// Full 3D orientation, rotating the local axes into world space. Locally,
+// forward is +x, up is +y and left is +z, so with no rotation it matches an
+// `Orientation2` with a pitch and a yaw of zero
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Orientation3 {
+  rotation: Quaternion<f32>,
+}
+
+impl Orientation3 {
+  pub fn identity() -> Self {
+    Self {
+      rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+    }
+  }
+
+  pub fn from_quaternion(rotation: Quaternion<f32>) -> Self {
+    Self {
+      rotation: rotation.normalize(),
+    }
+  }
+
+  // Falls back to another up vector when `forward` is (anti)parallel to `up`
+  pub fn look_to(forward: Vector3<f32>, up: Vector3<f32>) -> Self {
+    let forward = forward.normalize();
+    let mut left = forward.cross(up);
+    if left.magnitude2() < 1e-6 {
+      left = forward.cross(Vector3::unit_x());
+      if left.magnitude2() < 1e-6 {
+        left = forward.cross(Vector3::unit_z());
+      }
+    }
+    let left = left.normalize();
+    let up = left.cross(forward);
+    Self::from_quaternion(Matrix3::from_cols(forward, up, left).into())
+  }
+
+  pub fn look_at(
+    eye: Point3<f32>,
+    target: Point3<f32>,
+    up: Vector3<f32>,
+  ) -> Self {
+    Self::look_to(target - eye, up)
+  }
+
+  pub fn quaternion(&self) -> Quaternion<f32> {
+    self.rotation
+  }
+
+  pub fn forward(&self) -> Vector3<f32> {
+    self.rotation * Vector3::unit_x()
+  }
+  pub fn up(&self) -> Vector3<f32> {
+    self.rotation * Vector3::unit_y()
+  }
+  pub fn left(&self) -> Vector3<f32> {
+    self.rotation * Vector3::unit_z()
+  }
+
+  // Angle of the up vector around forward, from the up vector the same
+  // forward direction would have without any roll
+  pub fn roll(&self) -> Deg<f32> {
+    let forward = self.forward();
+    let level_left = forward.cross(Vector3::unit_y());
+    if level_left.magnitude2() < 1e-6 {
+      return Deg(0.0);
+    }
+    let level_left = level_left.normalize();
+    let level_up = level_left.cross(forward);
+    let up = self.up();
+    Deg::atan2(up.dot(level_left), up.dot(level_up))
+  }
+
+  // Turns by the pitch around the world vertical axis and by the yaw
+  // around the local left axis, the way `Orientation2` angles add up
+  pub fn rotated(&self, delta: Orientation2) -> Self {
+    Self::from_quaternion(
+      Quaternion::from_angle_y(-delta.pitch)
+        * self.rotation
+        * Quaternion::from_angle_z(delta.yaw),
+    )
+  }
+
+  pub fn rolled(&self, angle: Deg<f32>) -> Self {
+    Self::from_quaternion(self.rotation * Quaternion::from_angle_x(angle))
+  }
+
+  // Shortest rotation between both orientations, `t` going from 0 to 1
+  pub fn slerp(&self, other: Orientation3, t: f32) -> Self {
+    let mut target = other.rotation;
+    if self.rotation.dot(target) < 0.0 {
+      target = -target;
+    }
+    Self::from_quaternion(self.rotation.slerp(target, t))
+  }
+}
+
+impl From<Orientation2> for Orientation3 {
+  fn from(value: Orientation2) -> Self {
+    Self::from_quaternion(
+      Quaternion::from_angle_y(-value.pitch)
+        * Quaternion::from_angle_z(value.yaw),
+    )
+  }
+}
+
+// Drops the roll
+impl From<Orientation3> for Orientation2 {
+  fn from(value: Orientation3) -> Self {
+    let forward = value.forward();
+    let yaw = Deg::asin(forward.y.clamp(-1.0, 1.0));
+    let pitch = Deg::atan2(forward.z, forward.x);
+    Orientation2::new(pitch, yaw)
+  }
+}
@@ -1,11 +1,20 @@
 use std::rc::Rc;
 
+use cgmath::{EuclideanSpace, MetricSpace, Point3};
 use wgpu::{
   util::{BufferInitDescriptor, DeviceExt},
-  Buffer, BufferUsages, Device, IndexFormat, RenderPass,
+  Buffer, BufferUsages, Device, IndexFormat, Queue, RenderPass,
 };
 
-use super::{model::Vertex, texture::BloomTexture};
+use super::{
+  material::{Material, MaterialKey},
+  model::Vertex,
+};
+
+// Meshes get drawn in increasing draw category order
+pub const DRAW_CATEGORY_OPAQUE: usize = 0;
+pub const DRAW_CATEGORY_CUTOUT: usize = 1; // Fully opaque or fully see-through texels
+pub const DRAW_CATEGORY_TRANSPARENT: usize = 2;
 
 #[derive(Debug)]
 pub struct Mesh {
@@ -13,9 +22,12 @@ pub struct Mesh {
   index_buffer: Buffer,
   indices_count: u32,
 
-  texture: Rc<BloomTexture>,
+  material: Rc<Material>,
 
   draw_category: usize, // 0 gets drawn first etc, meshes with transparency have higher values
+  center: Point3<f32>,
+  // Only kept for transparent meshes, whose triangles get sorted back to front
+  triangles: Vec<([u16; 3], Point3<f32>)>, // (indices, centroid)
 }
 
 impl Mesh {
@@ -23,10 +35,10 @@ impl Mesh {
     label: &str,
     vertices: &[Vertex],
     indices: &[u16],
-    texture: Rc<BloomTexture>,
-    draw_category: usize,
+    material: Rc<Material>,
     device: &Device,
   ) -> Self {
+    let draw_category = material.desc().draw_category();
     let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
       label: Some(label),
       contents: bytemuck::cast_slice(vertices),
@@ -35,16 +47,44 @@ impl Mesh {
     let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
       label: Some(label),
       contents: bytemuck::cast_slice(indices),
-      usage: BufferUsages::INDEX,
+      usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
     });
+
+    let position =
+      |index: u16| Point3::from_vec(vertices[index as usize].position());
+    let center = if vertices.is_empty() {
+      Point3::origin()
+    } else {
+      Point3::centroid(
+        &vertices
+          .iter()
+          .map(|vertex| Point3::from_vec(vertex.position()))
+          .collect::<Vec<_>>(),
+      )
+    };
+    let triangles = match draw_category {
+      DRAW_CATEGORY_TRANSPARENT => indices
+        .chunks_exact(3)
+        .map(|triangle| {
+          let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+          let centroid =
+            Point3::centroid(&[position(a), position(b), position(c)]);
+          ([a, b, c], centroid)
+        })
+        .collect(),
+      _ => vec![],
+    };
+
     Self {
       vertex_buffer,
       index_buffer,
       indices_count: indices.len() as u32,
 
-      texture,
+      material,
 
       draw_category,
+      center,
+      triangles,
     }
   }
 
@@ -52,14 +92,87 @@ impl Mesh {
     &'selftime self,
     render_pass: &mut RenderPass<'selftime>,
   ) {
-    render_pass.set_bind_group(1, &self.texture.bind_group, &[]);
+    render_pass.set_bind_group(1, &self.material.bind_group, &[]);
+    self.render_geometry(render_pass);
+  }
+
+  // Draws the mesh without binding its material, for passes that only need
+  // the geometry, like the shadow pass
+  pub fn render_geometry<'selftime>(
+    &'selftime self,
+    render_pass: &mut RenderPass<'selftime>,
+  ) {
     render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
     render_pass
       .set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
     render_pass.draw_indexed(0..self.indices_count, 0, 0..1);
   }
 
+  // Rewrites the index buffer so that triangles further from the eye get
+  // drawn first, which transparent faces need to blend correctly
+  pub fn sort_faces(&self, eye: Point3<f32>, queue: &Queue) {
+    if self.triangles.is_empty() {
+      return;
+    }
+    let mut indices = back_to_front(&self.triangles, eye);
+    // Buffer writes must be a multiple of 4 bytes
+    if indices.len() % 2 == 1 {
+      indices.push(0);
+    }
+    queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+  }
+
   pub fn draw_category(&self) -> usize {
     self.draw_category
   }
+
+  pub fn is_transparent(&self) -> bool {
+    self.draw_category == DRAW_CATEGORY_TRANSPARENT
+  }
+
+  pub fn material_key(&self) -> MaterialKey {
+    self.material.desc().key()
+  }
+
+  pub fn center(&self) -> Point3<f32> {
+    self.center
+  }
+}
+
+// Indices of the triangles, the ones further from the eye first
+fn back_to_front(
+  triangles: &[([u16; 3], Point3<f32>)],
+  eye: Point3<f32>,
+) -> Vec<u16> {
+  let mut triangles = triangles.to_vec();
+  triangles.sort_by(|(_, c1), (_, c2)| {
+    eye.distance2(*c2).total_cmp(&eye.distance2(*c1))
+  });
+  triangles
+    .iter()
+    .flat_map(|(triangle, _)| *triangle)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn faces_are_sorted_back_to_front() {
+    // Three glass faces one behind the other along z, in one mesh
+    let triangles = [
+      ([0, 1, 2], Point3::new(0.5, 0.5, 1.0)),
+      ([3, 4, 5], Point3::new(0.5, 0.5, 3.0)),
+      ([6, 7, 8], Point3::new(0.5, 0.5, 2.0)),
+    ];
+    assert_eq!(
+      back_to_front(&triangles, Point3::new(0.5, 0.5, 10.0)),
+      vec![0, 1, 2, 6, 7, 8, 3, 4, 5]
+    );
+    assert_eq!(
+      back_to_front(&triangles, Point3::new(0.5, 0.5, -10.0)),
+      vec![3, 4, 5, 6, 7, 8, 0, 1, 2]
+    );
+  }
 }
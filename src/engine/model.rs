@@ -6,11 +6,17 @@ use wgpu::{BufferAddress, VertexBufferLayout, VertexStepMode};
 pub struct Vertex {
   position: [f32; 3],
   tex_coords: [f32; 2],
+  ao: f32,          // 1.0 is fully lit, 0.0 is fully occluded
+  normal: [f32; 3], // Zero for faces without an orientation
 }
 
 impl Vertex {
-  const ATTRIBS: [wgpu::VertexAttribute; 2] =
-    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+  const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x2,
+    2 => Float32,
+    3 => Float32x3
+  ];
   pub fn layout() -> VertexBufferLayout<'static> {
     use std::mem;
 
@@ -25,9 +31,26 @@ impl Vertex {
     Self {
       position: [x, y, z],
       tex_coords: [tx_x, tx_y],
+      ao: 1.0,
+      normal: [0.0, 0.0, 0.0],
     }
   }
 
+  pub fn position(&self) -> Vector3<f32> {
+    self.position.into()
+  }
+
+  pub fn ao(&self) -> f32 {
+    self.ao
+  }
+  pub fn set_ao(&mut self, ao: f32) {
+    self.ao = ao;
+  }
+
+  pub fn set_normal(&mut self, normal: Vector3<f32>) {
+    self.normal = normal.into();
+  }
+
   pub fn translate(&mut self, translation: Vector3<f32>) {
     let [ox, oy, oz] = self.position;
     let Vector3 { x, y, z } = translation;
@@ -0,0 +1,123 @@
+use std::{
+  collections::VecDeque,
+  fs::File,
+  io::{BufWriter, Write},
+};
+
+use anyhow::*;
+
+// Rolling statistics over the last samples of a timing, in seconds
+pub struct Timing {
+  samples: VecDeque<f32>,
+}
+
+impl Timing {
+  const WINDOW: usize = 120; // Frames
+
+  fn new() -> Self {
+    Self {
+      samples: VecDeque::with_capacity(Self::WINDOW),
+    }
+  }
+
+  fn push(&mut self, sample: f32) {
+    if self.samples.len() == Self::WINDOW {
+      self.samples.pop_front();
+    }
+    self.samples.push_back(sample);
+  }
+
+  pub fn last(&self) -> f32 {
+    self.samples.back().copied().unwrap_or(0.0)
+  }
+
+  pub fn average(&self) -> f32 {
+    if self.samples.is_empty() {
+      return 0.0;
+    }
+    self.samples.iter().sum::<f32>() / self.samples.len() as f32
+  }
+
+  pub fn min(&self) -> f32 {
+    self.samples.iter().copied().reduce(f32::min).unwrap_or(0.0)
+  }
+
+  pub fn max(&self) -> f32 {
+    self.samples.iter().copied().reduce(f32::max).unwrap_or(0.0)
+  }
+}
+
+// Named timings collected every frame. The BLOOM_PROFILE_CSV environment
+// variable names a file every sample also gets written to, one
+// `frame,timing,milliseconds` row each
+pub struct Profiler {
+  timings: Vec<(String, Timing)>, // In the order they were first recorded
+  frame: Vec<(String, f32)>,      // Recorded since the last frame ended
+  frame_index: u64,
+  csv: Option<BufWriter<File>>,
+}
+
+impl Profiler {
+  pub fn new() -> Self {
+    Self {
+      timings: vec![],
+      frame: vec![],
+      frame_index: 0,
+      csv: None,
+    }
+  }
+
+  pub fn from_env() -> Result<Self> {
+    let mut profiler = Self::new();
+    if let Some(path) = std::env::var_os("BLOOM_PROFILE_CSV") {
+      profiler.write_csv(File::create(path)?)?;
+    }
+    Ok(profiler)
+  }
+
+  pub fn write_csv(&mut self, file: File) -> Result<()> {
+    let mut csv = BufWriter::new(file);
+    writeln!(csv, "frame,timing,milliseconds")?;
+    self.csv = Some(csv);
+    Ok(())
+  }
+
+  // Timings recorded more than once in a frame add up
+  pub fn record(&mut self, name: &str, seconds: f32) {
+    match self.frame.iter_mut().find(|(timing, _)| timing == name) {
+      Some((_, total)) => *total += seconds,
+      None => self.frame.push((String::from(name), seconds)),
+    }
+  }
+
+  pub fn end_frame(&mut self) {
+    let frame = std::mem::take(&mut self.frame);
+    if let Some(csv) = &mut self.csv {
+      let written = frame.iter().try_for_each(|(name, seconds)| {
+        writeln!(csv, "{},{},{:.4}", self.frame_index, name, seconds * 1000.0)
+      });
+      if let Err(error) = written {
+        eprintln!("Stopped writing the profiler CSV: {}", error);
+        self.csv = None;
+      }
+    }
+    frame.into_iter().for_each(|(name, seconds)| {
+      match self.timings.iter_mut().find(|(timing, _)| *timing == name) {
+        Some((_, timing)) => timing.push(seconds),
+        None => {
+          let mut timing = Timing::new();
+          timing.push(seconds);
+          self.timings.push((name, timing));
+        }
+      }
+    });
+    self.frame_index += 1;
+  }
+
+  pub fn timings(&self) -> impl Iterator<Item = (&str, &Timing)> {
+    self
+      .timings
+      .iter()
+      .map(|(name, timing)| (name.as_str(), timing))
+  }
+}
@@ -1,19 +1,334 @@
+pub mod debug;
+pub mod font;
+pub mod fxaa;
+pub mod graph;
+pub mod hot_reload;
+pub mod hud;
+pub mod lighting;
+pub mod oit;
+pub mod post;
+pub mod sky;
+pub mod timestamps;
+
+use std::{collections::HashMap, time::Instant};
+
+use cgmath::Point3;
 use wgpu::{
-  include_wgsl, Backends, BindGroupLayout, BlendState, Color, ColorTargetState,
+  include_wgsl, Adapter, Backends, BindGroupLayout, BlendComponent,
+  BlendFactor, BlendOperation, BlendState, Color, ColorTargetState,
   ColorWrites, CommandEncoderDescriptor, CompareFunction, DepthBiasState,
-  DepthStencilState, Device, DeviceDescriptor, Extent3d, Face, Features,
+  DepthStencilState, Device, DeviceDescriptor, ErrorFilter, Face, Features,
   FragmentState, FrontFace, Limits, LoadOp, MultisampleState, Operations,
-  PipelineLayoutDescriptor, PolygonMode, PowerPreference, PrimitiveState,
-  PrimitiveTopology, Queue, RenderPassColorAttachment,
+  PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PowerPreference,
+  PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment,
   RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
-  RenderPipelineDescriptor, RequestAdapterOptions, StencilState, Surface,
-  SurfaceConfiguration, SurfaceError, Texture, TextureDescriptor,
-  TextureDimension, TextureFormat, TextureUsages, TextureView,
+  RenderPipelineDescriptor, RequestAdapterOptions, ShaderModule, StencilState,
+  Surface, SurfaceConfiguration, SurfaceError, TextureFormat, TextureUsages,
   TextureViewDescriptor, VertexState,
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
-use super::{camera::Camera, mesh::Mesh, model::Vertex, texture::BloomTexture};
+use self::{
+  debug::{DebugDraw, DebugPipelines, DebugView},
+  fxaa::Fxaa,
+  graph::{PassContext, RenderGraph, TextureDesc},
+  hot_reload::ShaderWatcher,
+  hud::Hud,
+  lighting::Lighting,
+  oit::Oit,
+  post::{PostProcessing, PostSettings, HDR_FORMAT},
+  sky::Sky,
+  timestamps::GpuTimer,
+};
+
+use super::{
+  camera::{Camera, CameraUniform, DepthRange},
+  material::{BlendMode, Material, MaterialKey, MaterialShader},
+  mesh::Mesh,
+  model::Vertex,
+};
+
+// How geometry fades into the sky with distance from the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fog {
+  Disabled,
+  Linear { start: f32, end: f32 },
+  Exponential { density: f32 },
+}
+
+// How overlapping transparent faces are blended together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transparency {
+  // Exact, but only as long as faces don't intersect
+  Sorted,
+  // Weighted blended order independent transparency, approximate but
+  // independent of the order faces are drawn in
+  WeightedBlended,
+}
+
+impl Transparency {
+  pub fn next(&self) -> Transparency {
+    match self {
+      Transparency::Sorted => Transparency::WeightedBlended,
+      Transparency::WeightedBlended => Transparency::Sorted,
+    }
+  }
+}
+
+// How jagged geometry edges are smoothed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Antialiasing {
+  Disabled,
+  // Multisampling with the given samples per pixel, lowered to the highest
+  // count the adapter supports. Also antialiases cutout blocks through alpha
+  // to coverage
+  Msaa { samples: u32 },
+  // Post pass smoothing the edges found in the final image, cheaper than
+  // MSAA but blurrier
+  Fxaa,
+}
+
+impl Antialiasing {
+  pub fn next(&self) -> Antialiasing {
+    match self {
+      Antialiasing::Disabled => Antialiasing::Msaa { samples: 4 },
+      Antialiasing::Msaa { .. } => Antialiasing::Fxaa,
+      Antialiasing::Fxaa => Antialiasing::Disabled,
+    }
+  }
+}
+
+pub struct RendererSettings {
+  pub shadows_enabled: bool, // Skips the shadow pass, for weaker machines
+  pub fog: Fog,
+  pub transparency: Transparency,
+  pub antialiasing: Antialiasing, // Applied on the next rendered frame
+  pub depth_range: DepthRange,    // Applied on the next rendered frame
+  pub post: PostSettings,
+  pub debug_view: DebugView,
+  pub chunk_bounds: bool, // Outlines the loaded chunks
+  pub crosshair: bool,
+  pub debug_panel: bool, // Filled by the engine, the renderer only stores it
+}
+
+impl Default for RendererSettings {
+  fn default() -> Self {
+    Self {
+      shadows_enabled: true,
+      // Fully fogged before the far plane, so distant geometry fades out
+      // instead of popping
+      fog: Fog::Linear {
+        start: 50.0,
+        end: 90.0,
+      },
+      transparency: Transparency::Sorted,
+      antialiasing: Antialiasing::Msaa { samples: 4 },
+      depth_range: DepthRange::Standard,
+      post: PostSettings::default(),
+      debug_view: DebugView::Shaded,
+      chunk_bounds: false,
+      crosshair: true,
+      debug_panel: false,
+    }
+  }
+}
+
+// Pipelines of the main pass, which all depend on its sample count
+struct ScenePipelines {
+  sample_count: u32,
+  depth_range: DepthRange,
+  format: TextureFormat,
+  // Created the first time a material with their key gets drawn
+  materials: HashMap<MaterialKey, RenderPipeline>,
+  sky: RenderPipeline,
+  debug: DebugPipelines,
+}
+
+impl ScenePipelines {
+  fn new(
+    sample_count: u32,
+    depth_range: DepthRange,
+    format: TextureFormat,
+    sources: &PipelineSources,
+    device: &Device,
+  ) -> Self {
+    // The sky is drawn first, behind everything, without touching the depth
+    let sky = device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("render_pipeline:sky"),
+      layout: Some(&sources.sky_layout),
+      vertex: VertexState {
+        module: &sources.sky_shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(FragmentState {
+        module: &sources.sky_shader,
+        entry_point: "fs_main",
+        targets: &[Some(ColorTargetState {
+          format,
+          blend: None,
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Always,
+        stencil: StencilState::default(),
+        bias: DepthBiasState::default(),
+      }),
+      multisample: MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    let debug = DebugPipelines::new(
+      sample_count,
+      depth_range,
+      format,
+      &sources.block_layout,
+      &sources.block_shader,
+      &sources.camera_bind_group_layout,
+      device,
+    );
+
+    Self {
+      sample_count,
+      depth_range,
+      format,
+      materials: HashMap::new(),
+      sky,
+      debug,
+    }
+  }
+
+  // Creates the pipelines of the materials which don't have one yet
+  fn prepare_materials(
+    &mut self,
+    keys: impl IntoIterator<Item = MaterialKey>,
+    sources: &PipelineSources,
+    device: &Device,
+  ) {
+    keys.into_iter().for_each(|key| {
+      self.materials.entry(key).or_insert_with(|| {
+        Self::create_material_pipeline(
+          key,
+          self.sample_count,
+          self.depth_range,
+          self.format,
+          sources,
+          device,
+        )
+      });
+    });
+  }
+
+  fn material(&self, key: MaterialKey) -> &RenderPipeline {
+    self
+      .materials
+      .get(&key)
+      .expect("material pipeline was never prepared")
+  }
+
+  fn create_material_pipeline(
+    key: MaterialKey,
+    sample_count: u32,
+    depth_range: DepthRange,
+    format: TextureFormat,
+    sources: &PipelineSources,
+    device: &Device,
+  ) -> RenderPipeline {
+    // Without multisampling there is no coverage to spread the alpha over,
+    // cutout texels are discarded instead
+    let cutout = key.blend == BlendMode::Cutout;
+    let fragment_entry = match (key.shader, cutout, sample_count) {
+      (MaterialShader::Lit, false, _) => "fs_main",
+      (MaterialShader::Lit, true, 1) => "fs_cutout",
+      (MaterialShader::Lit, true, _) => "fs_cutout_coverage",
+      (MaterialShader::Emissive, false, _) => "fs_emissive",
+      (MaterialShader::Emissive, true, 1) => "fs_emissive_cutout",
+      (MaterialShader::Emissive, true, _) => "fs_emissive_cutout_coverage",
+    };
+    let additive = BlendComponent {
+      src_factor: BlendFactor::One,
+      dst_factor: BlendFactor::One,
+      operation: BlendOperation::Add,
+    };
+    let blend = match key.blend {
+      BlendMode::Opaque | BlendMode::Cutout => None,
+      BlendMode::Translucent => Some(BlendState::ALPHA_BLENDING),
+      BlendMode::Additive => Some(BlendState {
+        color: additive,
+        alpha: additive,
+      }),
+    };
+    let label = format!("render_pipeline:material({:?})", key);
+    BloomRenderer::create_block_pipeline(
+      BlockPipelineDesc {
+        label: &label,
+        fragment_entry,
+        blend,
+        cull_mode: key.cull_mode,
+        depth_write_enabled: key.depth_write,
+        depth_compare: depth_range.compare(),
+        alpha_to_coverage_enabled: cutout && sample_count > 1,
+        ..BlockPipelineDesc::OPAQUE
+      },
+      sample_count,
+      &sources.block_layout,
+      &sources.block_shader,
+      format,
+      device,
+    )
+  }
+}
+
+// Layouts and shaders the sample count dependent pipelines are built from
+struct PipelineSources {
+  camera_bind_group_layout: BindGroupLayout,
+  block_layout: PipelineLayout,
+  block_shader: ShaderModule,
+  sky_layout: PipelineLayout,
+  sky_shader: ShaderModule,
+}
+
+// What differs between the pipelines drawing blocks
+struct BlockPipelineDesc<'a> {
+  label: &'a str,
+  fragment_entry: &'a str,
+  blend: Option<BlendState>,
+  polygon_mode: PolygonMode,
+  cull_mode: Option<Face>,
+  depth_write_enabled: bool,
+  depth_compare: CompareFunction,
+  alpha_to_coverage_enabled: bool,
+}
+
+impl BlockPipelineDesc<'_> {
+  // Filled, back face culled and depth tested, what most pipelines start from
+  const OPAQUE: BlockPipelineDesc<'static> = BlockPipelineDesc {
+    label: "render_pipeline:default",
+    fragment_entry: "fs_main",
+    blend: None,
+    polygon_mode: PolygonMode::Fill,
+    cull_mode: Some(Face::Back),
+    depth_write_enabled: true,
+    depth_compare: CompareFunction::Less,
+    alpha_to_coverage_enabled: false,
+  };
+}
 
 pub struct BloomRenderer {
   pub surface: Surface,
@@ -22,13 +337,34 @@ pub struct BloomRenderer {
   pub config: SurfaceConfiguration,
   pub size: PhysicalSize<u32>,
   pub camera: Camera,
+  camera_uniform: CameraUniform,
+  pub lighting: Lighting,
+  pub sky: Sky,
+  pub oit: Oit,
+  pub post: PostProcessing,
+  pub fxaa: Option<Fxaa>,
+  pub debug_draw: DebugDraw,
+  pub hud: Hud,
+  pub settings: RendererSettings,
+
+  pub material_bind_group_layout: BindGroupLayout,
 
-  pub texture_bind_group_layout: BindGroupLayout,
+  adapter: Adapter,
+  antialiasing: Antialiasing, // Currently applied, may lag the settings
+  sample_count: u32,
+  depth_range: DepthRange, // Same
 
-  depth_texture_view: TextureView,
-  depth_texture: Texture,
+  graph: RenderGraph,
+  chunk_bounds: Vec<(Point3<f32>, Point3<f32>)>,
+  block_outline: Vec<(Point3<f32>, Point3<f32>)>,
+  last_frame: Instant,
+  gpu_timer: Option<GpuTimer>, // When the adapter supports timestamps
+  shader_watcher: Option<ShaderWatcher>,
+  frame_time: f32, // Seconds, smoothed over the last frames
 
-  default_render_pipeline: RenderPipeline,
+  pipeline_sources: PipelineSources,
+  scene_pipelines: ScenePipelines,
+  shadow_render_pipeline: RenderPipeline,
 }
 
 impl BloomRenderer {
@@ -53,7 +389,14 @@ impl BloomRenderer {
       .request_device(
         &DeviceDescriptor {
           label: None,
-          ..Default::default()
+          // Lifts the sample counts allowed to whatever the adapter supports,
+          // allows drawing the wireframe debug view with lines and timing
+          // the passes on the GPU
+          features: adapter.features()
+            & (Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+              | Features::POLYGON_MODE_LINE
+              | Features::TIMESTAMP_QUERY),
+          limits: Limits::default(),
         },
         None,
       )
@@ -84,36 +427,45 @@ impl BloomRenderer {
     let camera_bind_group_layout = device.create_bind_group_layout(
       &Camera::bind_group_layout_desc(Some("camera_bind_group")),
     );
-    let texture_bind_group_layout =
-      device.create_bind_group_layout(&BloomTexture::bind_group_layout());
+    let material_bind_group_layout =
+      device.create_bind_group_layout(&Material::bind_group_layout());
+    let lighting_bind_group_layout = device.create_bind_group_layout(
+      &Lighting::bind_group_layout_desc(Some("lighting_bind_group")),
+    );
+    let sky_bind_group_layout = device.create_bind_group_layout(
+      &Sky::bind_group_layout_desc(Some("sky_bind_group")),
+    );
 
-    let render_pipeline_layout =
+    let block_pipeline_layout =
       device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: Some("render_pipeline_layout"),
         bind_group_layouts: &[
           &camera_bind_group_layout,
-          &texture_bind_group_layout,
+          &material_bind_group_layout,
+          &lighting_bind_group_layout,
+          &sky_bind_group_layout,
         ],
         push_constant_ranges: &[],
       });
-    let default_render_pipeline =
+    // Cascades bind a single view-projection matrix, just like the camera
+    let shadow_shader =
+      device.create_shader_module(include_wgsl!("shaders/shadow.wgsl"));
+    let shadow_pipeline_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("shadow_pipeline_layout"),
+        bind_group_layouts: &[&camera_bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let shadow_render_pipeline =
       device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("render_pipeline:default"),
-        layout: Some(&render_pipeline_layout),
+        label: Some("render_pipeline:shadow"),
+        layout: Some(&shadow_pipeline_layout),
         vertex: VertexState {
-          module: &default_shader,
+          module: &shadow_shader,
           entry_point: "vs_main",
           buffers: &[Vertex::layout()],
         },
-        fragment: Some(FragmentState {
-          module: &default_shader,
-          entry_point: "fs_main",
-          targets: &[Some(ColorTargetState {
-            format: config.format,
-            blend: Some(BlendState::ALPHA_BLENDING),
-            write_mask: ColorWrites::ALL,
-          })],
-        }),
+        fragment: None,
         primitive: PrimitiveState {
           topology: PrimitiveTopology::TriangleList,
           strip_index_format: None,
@@ -126,9 +478,13 @@ impl BloomRenderer {
         depth_stencil: Some(DepthStencilState {
           format: TextureFormat::Depth32Float,
           depth_write_enabled: true,
-          depth_compare: CompareFunction::Less,
+          depth_compare: CompareFunction::LessEqual,
           stencil: StencilState::default(),
-          bias: DepthBiasState::default(),
+          bias: DepthBiasState {
+            constant: 2,
+            slope_scale: 2.0,
+            clamp: 0.0,
+          },
         }),
         multisample: MultisampleState {
           count: 1,
@@ -137,53 +493,661 @@ impl BloomRenderer {
         },
         multiview: None,
       });
+
+    let sky_shader =
+      device.create_shader_module(include_wgsl!("shaders/sky.wgsl"));
+    let sky_pipeline_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("sky_pipeline_layout"),
+        bind_group_layouts: &[&sky_bind_group_layout],
+        push_constant_ranges: &[],
+      });
     let aspect_ratio = config.width as f32 / config.height as f32;
-    let camera = Camera::new(aspect_ratio, &camera_bind_group_layout, &device);
+    let camera = Camera::new(aspect_ratio);
+    let camera_uniform = CameraUniform::new(&camera_bind_group_layout, &device);
+    let lighting = Lighting::new(
+      &lighting_bind_group_layout,
+      &camera_bind_group_layout,
+      &device,
+    );
+    let sky = Sky::new(&sky_bind_group_layout, &device);
+    let oit = Oit::new(
+      config.width,
+      config.height,
+      &block_pipeline_layout,
+      &default_shader,
+      HDR_FORMAT,
+      1,
+      DepthRange::Standard,
+      &device,
+    );
+    let post = PostProcessing::new(
+      config.width,
+      config.height,
+      config.format,
+      &device,
+      &queue,
+    );
 
-    let (depth_texture, depth_texture_view) =
-      Self::create_depth_texture(config.width, config.height, &device);
+    let debug_draw = DebugDraw::new(&device);
+    let gpu_timer = device
+      .features()
+      .contains(Features::TIMESTAMP_QUERY)
+      .then(|| GpuTimer::new(&device, &queue));
+    let hud =
+      Hud::new(config.width, config.height, config.format, &device, &queue);
 
-    Self {
+    let pipeline_sources = PipelineSources {
+      camera_bind_group_layout,
+      block_layout: block_pipeline_layout,
+      block_shader: default_shader,
+      sky_layout: sky_pipeline_layout,
+      sky_shader,
+    };
+    // Everything depending on the sample count starts single sampled, the
+    // configured antialiasing is applied once the renderer exists
+    let scene_pipelines = ScenePipelines::new(
+      1,
+      DepthRange::Standard,
+      HDR_FORMAT,
+      &pipeline_sources,
+      &device,
+    );
+    let graph = Self::create_render_graph(config.width, config.height, 1);
+
+    let mut renderer = Self {
       surface,
       queue,
       device,
       config,
       size,
       camera,
+      camera_uniform,
+      lighting,
+      sky,
+      oit,
+      post,
+      fxaa: None,
+      debug_draw,
+      hud,
+      settings: RendererSettings::default(),
 
-      texture_bind_group_layout,
-      depth_texture,
-      depth_texture_view,
+      material_bind_group_layout,
+
+      adapter,
+      antialiasing: Antialiasing::Disabled,
+      sample_count: 1,
+      depth_range: DepthRange::Standard,
+
+      graph,
+      chunk_bounds: vec![],
+      block_outline: vec![],
+      last_frame: Instant::now(),
+      gpu_timer,
+      shader_watcher: ShaderWatcher::from_env(),
+      frame_time: 0.0,
+
+      pipeline_sources,
+      scene_pipelines,
+      shadow_render_pipeline,
+    };
+    renderer.apply_antialiasing();
+    renderer.apply_depth_range();
+    renderer
+  }
 
-      default_render_pipeline,
+  fn create_block_pipeline(
+    desc: BlockPipelineDesc,
+    sample_count: u32,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    format: TextureFormat,
+    device: &Device,
+  ) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some(desc.label),
+      layout: Some(layout),
+      vertex: VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[Vertex::layout()],
+      },
+      fragment: Some(FragmentState {
+        module: shader,
+        entry_point: desc.fragment_entry,
+        targets: &[Some(ColorTargetState {
+          format,
+          blend: desc.blend,
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: desc.cull_mode,
+        polygon_mode: desc.polygon_mode,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled: desc.depth_write_enabled,
+        depth_compare: desc.depth_compare,
+        stencil: StencilState::default(),
+        bias: DepthBiasState::default(),
+      }),
+      multisample: MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: desc.alpha_to_coverage_enabled,
+      },
+      multiview: None,
+    })
+  }
+
+  // Every target of the main pass and the OIT passes shares its sample count
+  fn sample_count_supported(&self, sample_count: u32) -> bool {
+    if sample_count == 1 {
+      return true;
+    }
+    // 4 samples are guaranteed for these formats, others need the adapter
+    // specific format features
+    if !self
+      .device
+      .features()
+      .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    {
+      return sample_count == 4;
     }
+    [
+      HDR_FORMAT,
+      TextureFormat::Depth32Float,
+      oit::ACCUMULATION_FORMAT,
+      oit::REVEALAGE_FORMAT,
+    ]
+    .into_iter()
+    .all(|format| {
+      self
+        .adapter
+        .get_texture_format_features(format)
+        .flags
+        .sample_count_supported(sample_count)
+    })
+  }
+
+  pub fn sample_count(&self) -> u32 {
+    self.sample_count
+  }
+
+  // Rebuilds everything depending on the antialiasing settings
+  fn apply_antialiasing(&mut self) {
+    let requested = match self.settings.antialiasing {
+      Antialiasing::Msaa { samples } => samples,
+      Antialiasing::Disabled | Antialiasing::Fxaa => 1,
+    };
+    self.sample_count = [16, 8, 4, 2, 1]
+      .into_iter()
+      .filter(|sample_count| *sample_count <= requested)
+      .find(|sample_count| self.sample_count_supported(*sample_count))
+      .unwrap_or(1);
+    self.antialiasing = self.settings.antialiasing;
+
+    self.rebuild_scene_pipelines();
+    self.fxaa = match self.antialiasing {
+      Antialiasing::Fxaa => Some(Fxaa::new(
+        self.config.width,
+        self.config.height,
+        self.config.format,
+        &self.device,
+      )),
+      _ => None,
+    };
+    self.graph = Self::create_render_graph(
+      self.config.width,
+      self.config.height,
+      self.sample_count,
+    );
   }
 
-  fn create_depth_texture(
+  // Rebuilds everything depending on the depth range settings
+  fn apply_depth_range(&mut self) {
+    self.depth_range = self.settings.depth_range;
+    self.camera.set_depth_range(self.depth_range);
+    self.rebuild_scene_pipelines();
+  }
+
+  // Everything built from the pipeline sources. The pipelines of the
+  // materials in use are created right away rather than when next drawn, so
+  // that shader reloads check them too
+  fn rebuild_scene_pipelines(&mut self) {
+    let material_keys: Vec<MaterialKey> =
+      self.scene_pipelines.materials.keys().copied().collect();
+    self.scene_pipelines = ScenePipelines::new(
+      self.sample_count,
+      self.depth_range,
+      HDR_FORMAT,
+      &self.pipeline_sources,
+      &self.device,
+    );
+    self.scene_pipelines.prepare_materials(
+      material_keys,
+      &self.pipeline_sources,
+      &self.device,
+    );
+    self.oit = Oit::new(
+      self.config.width,
+      self.config.height,
+      &self.pipeline_sources.block_layout,
+      &self.pipeline_sources.block_shader,
+      HDR_FORMAT,
+      self.sample_count,
+      self.depth_range,
+      &self.device,
+    );
+  }
+
+  // Rebuilds the pipelines with the shaders changed on disk. Shaders which
+  // fail to compile, or pipelines which fail to build with them, are
+  // reported and the previous ones kept
+  fn reload_shaders(&mut self) {
+    let Some(watcher) = &mut self.shader_watcher else {
+      return;
+    };
+    let reloaded: Vec<(&str, ShaderModule)> = watcher
+      .changed()
+      .into_iter()
+      .filter_map(|name| match watcher.load(name, &self.device) {
+        Ok(module) => Some((name, module)),
+        Err(error) => {
+          eprintln!(
+            "Failed to compile {}, keeping it as it was: {}",
+            name, error
+          );
+          None
+        }
+      })
+      .collect();
+    if reloaded.is_empty() {
+      return;
+    }
+
+    let previous: Vec<(&str, ShaderModule)> = reloaded
+      .into_iter()
+      .map(|(name, module)| (name, self.replace_shader(name, module)))
+      .collect();
+    self.device.push_error_scope(ErrorFilter::Validation);
+    self.rebuild_scene_pipelines();
+    match pollster::block_on(self.device.pop_error_scope()) {
+      None => previous
+        .iter()
+        .for_each(|(name, _)| println!("Reloaded {}", name)),
+      Some(error) => {
+        eprintln!(
+          "Failed to rebuild the pipelines, keeping the previous shaders: {}",
+          error
+        );
+        previous.into_iter().for_each(|(name, module)| {
+          self.replace_shader(name, module);
+        });
+        self.rebuild_scene_pipelines();
+      }
+    }
+  }
+
+  // Returns the shader replaced
+  fn replace_shader(
+    &mut self,
+    name: &str,
+    module: ShaderModule,
+  ) -> ShaderModule {
+    let sources = &mut self.pipeline_sources;
+    match name {
+      "sky.wgsl" => std::mem::replace(&mut sources.sky_shader, module),
+      _ => std::mem::replace(&mut sources.block_shader, module),
+    }
+  }
+
+  // Passes drawing each frame. Textures owned by other parts of the renderer
+  // are imported, and bound by name when the graph runs
+  fn create_render_graph(
     width: u32,
     height: u32,
-    device: &Device,
-  ) -> (Texture, TextureView) {
-    let depth_texture = device.create_texture(&TextureDescriptor {
-      label: Some("depth_texture"),
-      size: Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: TextureDimension::D2,
+    sample_count: u32,
+  ) -> RenderGraph {
+    let mut graph = RenderGraph::new(width, height);
+
+    let surface = graph.import("surface");
+    let scene = graph.import("scene");
+    let shadow_map = graph.import("shadow_map");
+    let oit_accumulation = graph.import("oit_accumulation");
+    let oit_revealage = graph.import("oit_revealage");
+    let fxaa_input = graph.import("fxaa_input");
+
+    let depth = graph.create_texture(TextureDesc {
+      label: "depth_texture",
       format: TextureFormat::Depth32Float,
+      sample_count,
       usage: TextureUsages::RENDER_ATTACHMENT,
-      view_formats: &[],
     });
-    let depth_texture_view =
-      depth_texture.create_view(&TextureViewDescriptor::default());
-    (depth_texture, depth_texture_view)
+    // Multisampled colour target, resolved into the scene after the main pass
+    let msaa = (sample_count > 1).then(|| {
+      graph.create_texture(TextureDesc {
+        label: "msaa_texture",
+        format: HDR_FORMAT,
+        sample_count,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+      })
+    });
+
+    graph.add_pass("shadow", &[], &[shadow_map], |ctx: &mut PassContext| {
+      let renderer = ctx.renderer;
+      if !renderer.settings.shadows_enabled {
+        return;
+      }
+      renderer.lighting.cascades.iter().for_each(|cascade| {
+        let mut shadow_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+              view: &cascade.view,
+              depth_ops: Some(Operations {
+                load: LoadOp::Clear(1.0),
+                store: true,
+              }),
+              stencil_ops: None,
+            }),
+          });
+        shadow_pass.set_pipeline(&renderer.shadow_render_pipeline);
+        shadow_pass.set_bind_group(0, &cascade.bind_group, &[]);
+        // Transparent blocks let the sun through
+        ctx
+          .meshes
+          .iter()
+          .filter(|mesh| !mesh.is_transparent())
+          .for_each(|mesh| mesh.render_geometry(&mut shadow_pass));
+      });
+    });
+
+    let main_writes = match msaa {
+      Some(msaa) => vec![scene, depth, msaa],
+      None => vec![scene, depth],
+    };
+    graph.add_pass(
+      "main",
+      &[shadow_map],
+      &main_writes,
+      move |ctx: &mut PassContext| {
+        let renderer = ctx.renderer;
+        let scene_view = ctx.resources.view(scene);
+        let (color_view, resolve_target) = match msaa {
+          Some(msaa) => (ctx.resources.view(msaa), Some(scene_view)),
+          None => (scene_view, None),
+        };
+        let debug_pipeline = renderer
+          .scene_pipelines
+          .debug
+          .view_pipeline(renderer.settings.debug_view);
+        let clear_color = match debug_pipeline {
+          Some(_) => Color::BLACK,
+          None => renderer.sky.clear_color(),
+        };
+        let mut render_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("render_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+              view: color_view,
+              resolve_target,
+              ops: Operations {
+                load: wgpu::LoadOp::Clear(clear_color),
+                store: true,
+              },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+              view: ctx.resources.view(depth),
+              depth_ops: Some(Operations {
+                load: LoadOp::Clear(renderer.depth_range.clear_value()),
+                store: true,
+              }),
+              stencil_ops: None,
+            }),
+          });
+        // Debug views draw every mesh the same way, without sky
+        if let Some(debug_pipeline) = debug_pipeline {
+          render_pass.set_pipeline(debug_pipeline);
+          render_pass.set_bind_group(
+            0,
+            &renderer.camera_uniform.bind_group,
+            &[],
+          );
+          render_pass.set_bind_group(2, &renderer.lighting.bind_group, &[]);
+          render_pass.set_bind_group(3, &renderer.sky.bind_group, &[]);
+          ctx
+            .meshes
+            .iter()
+            .for_each(|mesh| mesh.render(&mut render_pass));
+          return;
+        }
+
+        render_pass.set_pipeline(&renderer.scene_pipelines.sky);
+        render_pass.set_bind_group(0, &renderer.sky.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        render_pass.set_bind_group(0, &renderer.camera_uniform.bind_group, &[]);
+        render_pass.set_bind_group(2, &renderer.lighting.bind_group, &[]);
+        render_pass.set_bind_group(3, &renderer.sky.bind_group, &[]);
+        // World::meshes already orders the meshes by draw category, with
+        // transparent ones back to front
+        let sorted = renderer.settings.transparency == Transparency::Sorted;
+        let mut current_key = None;
+        ctx
+          .meshes
+          .iter()
+          .filter(|mesh| sorted || !mesh.is_transparent())
+          .for_each(|mesh| {
+            let key = mesh.material_key();
+            if current_key != Some(key) {
+              render_pass.set_pipeline(renderer.scene_pipelines.material(key));
+              current_key = Some(key);
+            }
+            mesh.render(&mut render_pass);
+          });
+      },
+    );
+
+    // Overlay drawn over the opaque scene, hidden by it but not hiding it
+    graph.add_pass(
+      "debug_lines",
+      &[depth],
+      &main_writes,
+      move |ctx: &mut PassContext| {
+        let renderer = ctx.renderer;
+        if renderer.debug_draw.is_empty() {
+          return;
+        }
+        let scene_view = ctx.resources.view(scene);
+        let (color_view, resolve_target) = match msaa {
+          Some(msaa) => (ctx.resources.view(msaa), Some(scene_view)),
+          None => (scene_view, None),
+        };
+        let mut lines_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("debug_lines_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+              view: color_view,
+              resolve_target,
+              ops: Operations {
+                load: LoadOp::Load,
+                store: true,
+              },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+              view: ctx.resources.view(depth),
+              depth_ops: Some(Operations {
+                load: LoadOp::Load,
+                store: true,
+              }),
+              stencil_ops: None,
+            }),
+          });
+        lines_pass.set_pipeline(&renderer.scene_pipelines.debug.lines);
+        lines_pass.set_bind_group(0, &renderer.camera_uniform.bind_group, &[]);
+        renderer.debug_draw.render(&mut lines_pass);
+      },
+    );
+
+    graph.add_pass(
+      "oit_accumulate",
+      &[shadow_map, depth],
+      &[oit_accumulation, oit_revealage],
+      move |ctx: &mut PassContext| {
+        let renderer = ctx.renderer;
+        if !renderer.weighted_blended_transparency() {
+          return;
+        }
+        let mut accumulate_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("oit_accumulate_pass"),
+            color_attachments: &renderer.oit.color_attachments(),
+            // Opaque geometry still hides the transparent faces behind it
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+              view: ctx.resources.view(depth),
+              depth_ops: Some(Operations {
+                load: LoadOp::Load,
+                store: true,
+              }),
+              stencil_ops: None,
+            }),
+          });
+        accumulate_pass.set_pipeline(&renderer.oit.accumulate_pipeline);
+        accumulate_pass.set_bind_group(
+          0,
+          &renderer.camera_uniform.bind_group,
+          &[],
+        );
+        accumulate_pass.set_bind_group(2, &renderer.lighting.bind_group, &[]);
+        accumulate_pass.set_bind_group(3, &renderer.sky.bind_group, &[]);
+        ctx
+          .meshes
+          .iter()
+          .filter(|mesh| mesh.is_transparent())
+          .for_each(|mesh| mesh.render(&mut accumulate_pass));
+      },
+    );
+
+    graph.add_pass(
+      "oit_composite",
+      &[oit_accumulation, oit_revealage],
+      &[scene],
+      move |ctx: &mut PassContext| {
+        let renderer = ctx.renderer;
+        if !renderer.weighted_blended_transparency() {
+          return;
+        }
+        let mut composite_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("oit_composite_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+              view: ctx.resources.view(scene),
+              resolve_target: None,
+              ops: Operations {
+                load: LoadOp::Load,
+                store: true,
+              },
+            })],
+            depth_stencil_attachment: None,
+          });
+        composite_pass.set_pipeline(&renderer.oit.composite_pipeline);
+        composite_pass.set_bind_group(
+          0,
+          &renderer.oit.composite_bind_group,
+          &[],
+        );
+        composite_pass.draw(0..3, 0..1);
+      },
+    );
+
+    // With FXAA, post-processing writes into its input instead of the surface
+    graph.add_pass(
+      "post",
+      &[scene],
+      &[fxaa_input, surface],
+      move |ctx: &mut PassContext| {
+        let renderer = ctx.renderer;
+        let output = match renderer.fxaa {
+          Some(_) => ctx.resources.view(fxaa_input),
+          None => ctx.resources.view(surface),
+        };
+        // Debug views are shown with their colours untouched
+        let effects = match renderer.settings.debug_view {
+          DebugView::Shaded => &renderer.settings.post.effects[..],
+          _ => &[],
+        };
+        renderer.post.render(effects, ctx.encoder, output);
+      },
+    );
+
+    graph.add_pass(
+      "fxaa",
+      &[fxaa_input],
+      &[surface],
+      move |ctx: &mut PassContext| {
+        let Some(fxaa) = &ctx.renderer.fxaa else {
+          return;
+        };
+        let mut fxaa_pass =
+          ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("fxaa_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+              view: ctx.resources.view(surface),
+              resolve_target: None,
+              ops: Operations {
+                load: LoadOp::Load,
+                store: true,
+              },
+            })],
+            depth_stencil_attachment: None,
+          });
+        fxaa_pass.set_pipeline(&fxaa.pipeline);
+        fxaa_pass.set_bind_group(0, &fxaa.bind_group, &[]);
+        fxaa_pass.draw(0..3, 0..1);
+      },
+    );
+
+    // Drawn last, over the final image
+    graph.add_pass("hud", &[], &[surface], move |ctx: &mut PassContext| {
+      let hud = &ctx.renderer.hud;
+      if hud.is_empty() {
+        return;
+      }
+      let mut hud_pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("hud_pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+          view: ctx.resources.view(surface),
+          resolve_target: None,
+          ops: Operations {
+            load: LoadOp::Load,
+            store: true,
+          },
+        })],
+        depth_stencil_attachment: None,
+      });
+      hud.render(&mut hud_pass);
+    });
+
+    graph
   }
 
   pub fn render(&mut self, meshes: &[&Mesh]) -> Result<(), SurfaceError> {
+    if self.settings.antialiasing != self.antialiasing {
+      self.apply_antialiasing();
+    }
+    if self.settings.depth_range != self.depth_range {
+      self.apply_depth_range();
+    }
+    self.reload_shaders();
+
     let output = self.surface.get_current_texture()?;
     let view = output
       .texture
@@ -196,45 +1160,142 @@ impl BloomRenderer {
           label: Some("render_encoder"),
         });
 
-    self.camera.update_proj_matrix(&self.queue);
+    self.camera_uniform.update(&self.camera, &self.queue);
+    self.lighting.update(
+      &self.camera,
+      self.settings.shadows_enabled,
+      &self.queue,
+    );
+    self.sky.update(
+      &self.camera,
+      self.lighting.sun_direction(),
+      self.settings.fog,
+      &self.queue,
+    );
+    self.post.update(&self.settings.post, &self.queue);
 
-    {
-      let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-        label: Some("render_pass"),
-        color_attachments: &[Some(RenderPassColorAttachment {
-          view: &view,
-          resolve_target: None,
-          ops: Operations {
-            load: wgpu::LoadOp::Clear(Color {
-              r: 0.1,
-              g: 0.2,
-              b: 0.3,
-              a: 1.0,
-            }),
-            store: true,
-          },
-        })],
-        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-          view: &self.depth_texture_view,
-          depth_ops: Some(Operations {
-            load: LoadOp::Clear(1.0),
-            store: true,
-          }),
-          stencil_ops: None,
-        }),
+    if self.settings.chunk_bounds {
+      self.chunk_bounds.iter().for_each(|(min, max)| {
+        self.debug_draw.aabb(*min, *max, [1.0, 0.85, 0.1]);
       });
-      render_pass.set_pipeline(&self.default_render_pipeline);
-      render_pass.set_bind_group(0, &self.camera.camera_bind_group, &[]);
+    }
+    self.block_outline.iter().for_each(|(min, max)| {
+      self.debug_draw.aabb(*min, *max, [0.1, 0.1, 0.1]);
+    });
+    self.debug_draw.upload(&self.device, &self.queue);
+    if self.settings.crosshair {
+      self.draw_crosshair();
+    }
+    self.hud.upload(&self.device, &self.queue);
 
-      meshes.iter().for_each(|mesh| mesh.render(&mut render_pass));
+    if self.settings.transparency == Transparency::Sorted {
+      let eye = self.camera.position();
+      meshes
+        .iter()
+        .filter(|mesh| mesh.is_transparent())
+        .for_each(|mesh| mesh.sort_faces(eye, &self.queue));
+    }
+
+    self.scene_pipelines.prepare_materials(
+      meshes.iter().map(|mesh| mesh.material_key()),
+      &self.pipeline_sources,
+      &self.device,
+    );
+    self.graph.prepare(&self.device);
+    let mut imports = vec![
+      ("surface", &view),
+      ("scene", self.post.scene_view()),
+      ("oit_accumulation", &self.oit.accumulation_view),
+      ("oit_revealage", &self.oit.revealage_view),
+    ];
+    if let Some(fxaa) = &self.fxaa {
+      imports.push(("fxaa_input", &fxaa.scene_view));
+    }
+    let timed = match &mut self.gpu_timer {
+      Some(timer) => timer.begin_frame(&self.device),
+      None => false,
+    };
+    let timer = self.gpu_timer.as_ref().filter(|_| timed);
+    self
+      .graph
+      .execute(&mut encoder, &imports, self, meshes, timer);
+    if let Some(timer) = self.gpu_timer.as_mut().filter(|_| timed) {
+      timer.resolve(self.graph.pass_names(), &mut encoder);
     }
 
     self.queue.submit(std::iter::once(encoder.finish()));
     output.present();
+    if let Some(timer) = self.gpu_timer.as_ref().filter(|_| timed) {
+      timer.map();
+    }
+    self.debug_draw.clear();
+    self.hud.clear();
+
+    let frame_time = self.last_frame.elapsed().as_secs_f32();
+    self.last_frame = Instant::now();
+    self.frame_time = if self.frame_time == 0.0 {
+      frame_time
+    } else {
+      self.frame_time * 0.9 + frame_time * 0.1
+    };
 
     Ok(())
   }
 
+  fn draw_crosshair(&mut self) {
+    let (x, y) = (
+      self.hud.width() as f32 / 2.0,
+      self.hud.height() as f32 / 2.0,
+    );
+    let (length, thickness) = (10.0, 2.0);
+    let color = [1.0, 1.0, 1.0, 0.8];
+    self.hud.rect(
+      x - length,
+      y - thickness / 2.0,
+      length * 2.0,
+      thickness,
+      color,
+    );
+    self.hud.rect(
+      x - thickness / 2.0,
+      y - length,
+      thickness,
+      length * 2.0,
+      color,
+    );
+  }
+
+  // Time between the last rendered frames, in seconds
+  pub fn frame_time(&self) -> f32 {
+    self.frame_time
+  }
+
+  // GPU time of each render graph pass, in seconds. Lags a few frames behind
+  // and stays empty without timestamp query support
+  pub fn gpu_pass_times(&self) -> &[(&'static str, f32)] {
+    match &self.gpu_timer {
+      Some(timer) => timer.pass_times(),
+      None => &[],
+    }
+  }
+
+  // Transparent faces go through the OIT passes, unless a debug view
+  // already drew them in the main pass
+  fn weighted_blended_transparency(&self) -> bool {
+    self.settings.transparency == Transparency::WeightedBlended
+      && self.settings.debug_view == DebugView::Shaded
+  }
+
+  // Bounds of the loaded chunks, outlined when enabled in the settings
+  pub fn set_chunk_bounds(&mut self, bounds: Vec<(Point3<f32>, Point3<f32>)>) {
+    self.chunk_bounds = bounds;
+  }
+
+  // Boxes outlining the targeted block, empty when no block is targeted
+  pub fn set_block_outline(&mut self, boxes: Vec<(Point3<f32>, Point3<f32>)>) {
+    self.block_outline = boxes;
+  }
+
   pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
     if new_size.width > 0 && new_size.height > 0 {
       self.size = new_size;
@@ -245,13 +1306,17 @@ impl BloomRenderer {
       let new_aspect = new_size.width as f32 / new_size.height as f32;
       self.camera.update_aspect(new_aspect);
 
-      let (depth_texture, depth_texture_view) = Self::create_depth_texture(
-        new_size.width,
-        new_size.height,
-        &self.device,
-      );
-      self.depth_texture = depth_texture;
-      self.depth_texture_view = depth_texture_view;
+      self.graph.resize(new_size.width, new_size.height);
+      self
+        .oit
+        .resize(new_size.width, new_size.height, &self.device);
+      self
+        .post
+        .resize(new_size.width, new_size.height, &self.device);
+      if let Some(fxaa) = &mut self.fxaa {
+        fxaa.resize(new_size.width, new_size.height, &self.device);
+      }
+      self.hud.resize(new_size.width, new_size.height);
     }
   }
 }
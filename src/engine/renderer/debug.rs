@@ -0,0 +1,377 @@
+use cgmath::{Angle, InnerSpace, MetricSpace, Point3, Rad, Vector3};
+use wgpu::{
+  include_wgsl, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation,
+  BlendState, Buffer, BufferAddress, BufferDescriptor, BufferUsages,
+  ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
+  DepthStencilState, Device, FragmentState, FrontFace, MultisampleState,
+  PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+  PrimitiveTopology, Queue, RenderPass, RenderPipeline,
+  RenderPipelineDescriptor, ShaderModule, StencilState, TextureFormat,
+  VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::engine::camera::DepthRange;
+
+use super::{BlockPipelineDesc, BloomRenderer};
+
+// What the main pass shows instead of the shaded scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugView {
+  Shaded,
+  // Edges of every face, hidden or not
+  Wireframe,
+  Normals,
+  // Each chunk gets its own flat colour
+  ChunkColors,
+  // Brighter pixels had more fragments drawn on them
+  Overdraw,
+}
+
+impl DebugView {
+  pub fn next(&self) -> DebugView {
+    match self {
+      DebugView::Shaded => DebugView::Wireframe,
+      DebugView::Wireframe => DebugView::Normals,
+      DebugView::Normals => DebugView::ChunkColors,
+      DebugView::ChunkColors => DebugView::Overdraw,
+      DebugView::Overdraw => DebugView::Shaded,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+  position: [f32; 3],
+  color: [f32; 3],
+}
+
+impl LineVertex {
+  const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x3
+  ];
+  pub fn layout() -> VertexBufferLayout<'static> {
+    use std::mem;
+
+    VertexBufferLayout {
+      array_stride: mem::size_of::<Self>() as BufferAddress,
+      step_mode: VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBS,
+    }
+  }
+
+  pub fn new(position: Point3<f32>, color: [f32; 3]) -> Self {
+    Self {
+      position: position.into(),
+      color,
+    }
+  }
+}
+
+// Immediate mode gizmos, made of coloured line segments drawn over the scene.
+// Shapes are added while updating a frame, drawn after the world and then
+// cleared, so they must be added again every frame to stay visible
+pub struct DebugDraw {
+  vertices: Vec<LineVertex>,
+  buffer: Buffer,
+  capacity: usize,   // In vertices
+  vertex_count: u32, // Uploaded for the current frame
+}
+
+impl DebugDraw {
+  const INITIAL_CAPACITY: usize = 1024;
+  const CIRCLE_SEGMENTS: usize = 24;
+
+  pub fn new(device: &Device) -> Self {
+    Self {
+      vertices: vec![],
+      buffer: Self::create_buffer(Self::INITIAL_CAPACITY, device),
+      capacity: Self::INITIAL_CAPACITY,
+      vertex_count: 0,
+    }
+  }
+
+  fn create_buffer(capacity: usize, device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some("debug_lines_buffer"),
+      size: (capacity * std::mem::size_of::<LineVertex>()) as u64,
+      usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+
+  pub fn line(
+    &mut self,
+    start: Point3<f32>,
+    end: Point3<f32>,
+    color: [f32; 3],
+  ) {
+    self.vertices.push(LineVertex::new(start, color));
+    self.vertices.push(LineVertex::new(end, color));
+  }
+
+  // Axis aligned box between two opposite corners
+  pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 3]) {
+    let corner = |x: bool, y: bool, z: bool| {
+      Point3::new(
+        if x { max.x } else { min.x },
+        if y { max.y } else { min.y },
+        if z { max.z } else { min.z },
+      )
+    };
+    [false, true].into_iter().for_each(|a| {
+      [false, true].into_iter().for_each(|b| {
+        self.line(corner(false, a, b), corner(true, a, b), color);
+        self.line(corner(a, false, b), corner(a, true, b), color);
+        self.line(corner(a, b, false), corner(a, b, true), color);
+      });
+    });
+  }
+
+  // Outlined by its three great circles along the axis planes
+  pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 3]) {
+    self.circle(center, Vector3::unit_x(), Vector3::unit_y(), radius, color);
+    self.circle(center, Vector3::unit_y(), Vector3::unit_z(), radius, color);
+    self.circle(center, Vector3::unit_z(), Vector3::unit_x(), radius, color);
+  }
+
+  // Circle in the plane spanned by the two unit axes
+  fn circle(
+    &mut self,
+    center: Point3<f32>,
+    axis1: Vector3<f32>,
+    axis2: Vector3<f32>,
+    radius: f32,
+    color: [f32; 3],
+  ) {
+    let point = |segment: usize| {
+      let angle =
+        Rad::full_turn() * segment as f32 / Self::CIRCLE_SEGMENTS as f32;
+      center + (axis1 * angle.cos() + axis2 * angle.sin()) * radius
+    };
+    (0..Self::CIRCLE_SEGMENTS).for_each(|segment| {
+      self.line(point(segment), point(segment + 1), color);
+    });
+  }
+
+  // Line from `start` with a four sided head pointing at `end`
+  pub fn arrow(
+    &mut self,
+    start: Point3<f32>,
+    end: Point3<f32>,
+    color: [f32; 3],
+  ) {
+    self.line(start, end, color);
+    let length = start.distance(end);
+    if length == 0.0 {
+      return;
+    }
+    let direction = (end - start) / length;
+    // Any axis not parallel to the arrow gives the sides of the head
+    let reference = match direction.y.abs() > 0.9 {
+      true => Vector3::unit_x(),
+      false => Vector3::unit_y(),
+    };
+    let side1 = direction.cross(reference).normalize();
+    let side2 = direction.cross(side1);
+    let head_length = (length * 0.25).min(0.5);
+    let head_base = end - direction * head_length;
+    [side1, -side1, side2, -side2].into_iter().for_each(|side| {
+      self.line(end, head_base + side * head_length * 0.4, color);
+    });
+  }
+
+  // Copies the lines collected so far to the GPU, growing the buffer when
+  // they don't fit
+  pub fn upload(&mut self, device: &Device, queue: &Queue) {
+    if self.vertices.len() > self.capacity {
+      self.capacity = self.vertices.len().next_power_of_two();
+      self.buffer = Self::create_buffer(self.capacity, device);
+    }
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+    self.vertex_count = self.vertices.len() as u32;
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.vertex_count == 0
+  }
+
+  pub fn clear(&mut self) {
+    self.vertices.clear();
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+    render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+}
+
+// Pipelines of the debug views and lines, sharing the main pass sample count
+pub struct DebugPipelines {
+  wireframe: RenderPipeline,
+  normals: RenderPipeline,
+  chunk_colors: RenderPipeline,
+  overdraw: RenderPipeline,
+  pub lines: RenderPipeline,
+}
+
+impl DebugPipelines {
+  pub fn new(
+    sample_count: u32,
+    depth_range: DepthRange,
+    format: TextureFormat,
+    block_layout: &PipelineLayout,
+    block_shader: &ShaderModule,
+    camera_bind_group_layout: &BindGroupLayout,
+    device: &Device,
+  ) -> Self {
+    // Line polygon mode is an optional feature, without it a shader outlines
+    // the block faces instead
+    let line_polygon_mode = device
+      .features()
+      .contains(wgpu::Features::POLYGON_MODE_LINE);
+    let wireframe = BloomRenderer::create_block_pipeline(
+      BlockPipelineDesc {
+        label: "render_pipeline:debug_wireframe",
+        fragment_entry: match line_polygon_mode {
+          true => "fs_debug_wireframe",
+          false => "fs_debug_wireframe_fallback",
+        },
+        blend: None,
+        polygon_mode: match line_polygon_mode {
+          true => PolygonMode::Line,
+          false => PolygonMode::Fill,
+        },
+        cull_mode: None,
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Always,
+        alpha_to_coverage_enabled: false,
+      },
+      sample_count,
+      block_layout,
+      block_shader,
+      format,
+      device,
+    );
+    let normals = BloomRenderer::create_block_pipeline(
+      BlockPipelineDesc {
+        label: "render_pipeline:debug_normals",
+        fragment_entry: "fs_debug_normals",
+        blend: None,
+        depth_compare: depth_range.compare(),
+        ..BlockPipelineDesc::OPAQUE
+      },
+      sample_count,
+      block_layout,
+      block_shader,
+      format,
+      device,
+    );
+    let chunk_colors = BloomRenderer::create_block_pipeline(
+      BlockPipelineDesc {
+        label: "render_pipeline:debug_chunk_colors",
+        fragment_entry: "fs_debug_chunks",
+        blend: None,
+        depth_compare: depth_range.compare(),
+        ..BlockPipelineDesc::OPAQUE
+      },
+      sample_count,
+      block_layout,
+      block_shader,
+      format,
+      device,
+    );
+    let additive = BlendComponent {
+      src_factor: BlendFactor::One,
+      dst_factor: BlendFactor::One,
+      operation: BlendOperation::Add,
+    };
+    let overdraw = BloomRenderer::create_block_pipeline(
+      BlockPipelineDesc {
+        label: "render_pipeline:debug_overdraw",
+        fragment_entry: "fs_debug_overdraw",
+        blend: Some(BlendState {
+          color: additive,
+          alpha: additive,
+        }),
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Always,
+        ..BlockPipelineDesc::OPAQUE
+      },
+      sample_count,
+      block_layout,
+      block_shader,
+      format,
+      device,
+    );
+
+    let lines_shader =
+      device.create_shader_module(include_wgsl!("../shaders/debug_lines.wgsl"));
+    let lines_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("debug_lines_pipeline_layout"),
+        bind_group_layouts: &[camera_bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let lines = device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("render_pipeline:debug_lines"),
+      layout: Some(&lines_layout),
+      vertex: VertexState {
+        module: &lines_shader,
+        entry_point: "vs_main",
+        buffers: &[LineVertex::layout()],
+      },
+      fragment: Some(FragmentState {
+        module: &lines_shader,
+        entry_point: "fs_main",
+        targets: &[Some(ColorTargetState {
+          format,
+          blend: None,
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::LineList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled: false,
+        depth_compare: depth_range.compare_equal(),
+        stencil: StencilState::default(),
+        bias: DepthBiasState::default(),
+      }),
+      multisample: MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    Self {
+      wireframe,
+      normals,
+      chunk_colors,
+      overdraw,
+      lines,
+    }
+  }
+
+  // None for the shaded view, which uses the regular pipelines
+  pub fn view_pipeline(&self, view: DebugView) -> Option<&RenderPipeline> {
+    match view {
+      DebugView::Shaded => None,
+      DebugView::Wireframe => Some(&self.wireframe),
+      DebugView::Normals => Some(&self.normals),
+      DebugView::ChunkColors => Some(&self.chunk_colors),
+      DebugView::Overdraw => Some(&self.overdraw),
+    }
+  }
+}
@@ -0,0 +1,197 @@
+use wgpu::{
+  include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry,
+  BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+  BindingResource, BindingType, ColorTargetState, ColorWrites, Device,
+  Extent3d, FilterMode, FragmentState, FrontFace, MultisampleState,
+  PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+  RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+  SamplerDescriptor, ShaderStages, Texture, TextureDescriptor,
+  TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+  TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+// Fast approximate anti-aliasing. The scene is rendered into an offscreen
+// target, which gets smoothed along its edges while drawn to the surface
+pub struct Fxaa {
+  format: TextureFormat,
+
+  scene_texture: Texture,
+  pub scene_view: TextureView,
+  sampler: Sampler,
+
+  bind_group_layout: BindGroupLayout,
+  pub bind_group: BindGroup,
+
+  pub pipeline: RenderPipeline,
+}
+
+impl Fxaa {
+  pub fn new(
+    width: u32,
+    height: u32,
+    surface_format: TextureFormat,
+    device: &Device,
+  ) -> Self {
+    let bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("fxaa_bind_group_layout"),
+        entries: &Self::BIND_LAYOUT_ENTRIES,
+      });
+
+    let shader =
+      device.create_shader_module(include_wgsl!("../shaders/fxaa.wgsl"));
+    let pipeline_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("fxaa_pipeline_layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("render_pipeline:fxaa"),
+      layout: Some(&pipeline_layout),
+      vertex: VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(ColorTargetState {
+          format: surface_format,
+          blend: None,
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: None,
+      multisample: MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    // Edges are found between texels, so the scene is sampled bilinearly
+    let sampler = device.create_sampler(&SamplerDescriptor {
+      label: Some("fxaa_sampler"),
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Nearest,
+      ..Default::default()
+    });
+
+    let (scene_texture, scene_view) =
+      Self::create_scene_texture(width, height, surface_format, device);
+    let bind_group = Self::create_bind_group(
+      &bind_group_layout,
+      &scene_view,
+      &sampler,
+      device,
+    );
+
+    Self {
+      format: surface_format,
+
+      scene_texture,
+      scene_view,
+      sampler,
+
+      bind_group_layout,
+      bind_group,
+
+      pipeline,
+    }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 2] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Sampler(SamplerBindingType::Filtering),
+      count: None,
+    },
+  ];
+
+  fn create_scene_texture(
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    device: &Device,
+  ) -> (Texture, TextureView) {
+    let texture = device.create_texture(&TextureDescriptor {
+      label: Some("fxaa_scene_texture"),
+      size: Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: TextureDimension::D2,
+      format,
+      usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    (texture, view)
+  }
+
+  fn create_bind_group(
+    layout: &BindGroupLayout,
+    scene_view: &TextureView,
+    sampler: &Sampler,
+    device: &Device,
+  ) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+      label: Some("fxaa_bind_group"),
+      layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: BindingResource::TextureView(scene_view),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: BindingResource::Sampler(sampler),
+        },
+      ],
+    })
+  }
+
+  // The scene texture must always match the size of the surface
+  pub fn resize(&mut self, width: u32, height: u32, device: &Device) {
+    let (scene_texture, scene_view) =
+      Self::create_scene_texture(width, height, self.format, device);
+    self.bind_group = Self::create_bind_group(
+      &self.bind_group_layout,
+      &scene_view,
+      &self.sampler,
+      device,
+    );
+    self.scene_texture = scene_texture;
+    self.scene_view = scene_view;
+  }
+}
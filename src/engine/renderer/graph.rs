@@ -0,0 +1,254 @@
+use wgpu::{
+  CommandEncoder, Device, Extent3d, Texture, TextureDescriptor,
+  TextureDimension, TextureFormat, TextureUsages, TextureView,
+  TextureViewDescriptor,
+};
+
+use crate::engine::mesh::Mesh;
+
+use super::{timestamps::GpuTimer, BloomRenderer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceId(usize);
+
+// Textures allocated by the graph always match the size of the surface
+#[derive(Debug, Clone, Copy)]
+pub struct TextureDesc {
+  pub label: &'static str,
+  pub format: TextureFormat,
+  pub sample_count: u32,
+  pub usage: TextureUsages,
+}
+
+enum Resource {
+  // Allocated on first use and dropped whenever the surface is resized
+  Texture {
+    desc: TextureDesc,
+    allocated: Option<(Texture, TextureView)>,
+  },
+  // Owned elsewhere and bound by name when the graph runs. Imports which are
+  // never bound still order the passes using them
+  Imported {
+    name: &'static str,
+  },
+}
+
+// Everything a pass gets to record its commands
+pub struct PassContext<'a> {
+  pub encoder: &'a mut CommandEncoder,
+  pub resources: &'a PassResources<'a>,
+  pub renderer: &'a BloomRenderer,
+  pub meshes: &'a [&'a Mesh],
+}
+
+pub struct PassResources<'a> {
+  views: Vec<Option<&'a TextureView>>,
+}
+
+impl<'a> PassResources<'a> {
+  pub fn view(&self, id: ResourceId) -> &'a TextureView {
+    self.views[id.0].expect("render graph resource was never bound")
+  }
+}
+
+struct Pass {
+  name: &'static str,
+  reads: Vec<ResourceId>,
+  writes: Vec<ResourceId>,
+  run: Box<dyn Fn(&mut PassContext)>,
+}
+
+// Passes declare the resources they read and write, and run in an order
+// where every resource is fully written before it is read. Passes writing
+// the same resource keep the order they were added in
+pub struct RenderGraph {
+  width: u32,
+  height: u32,
+  resources: Vec<Resource>,
+  passes: Vec<Pass>,
+  order: Vec<usize>,
+}
+
+impl RenderGraph {
+  pub fn new(width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      resources: vec![],
+      passes: vec![],
+      order: vec![],
+    }
+  }
+
+  pub fn create_texture(&mut self, desc: TextureDesc) -> ResourceId {
+    self.resources.push(Resource::Texture {
+      desc,
+      allocated: None,
+    });
+    ResourceId(self.resources.len() - 1)
+  }
+
+  pub fn import(&mut self, name: &'static str) -> ResourceId {
+    self.resources.push(Resource::Imported { name });
+    ResourceId(self.resources.len() - 1)
+  }
+
+  pub fn add_pass(
+    &mut self,
+    name: &'static str,
+    reads: &[ResourceId],
+    writes: &[ResourceId],
+    run: impl Fn(&mut PassContext) + 'static,
+  ) {
+    self.passes.push(Pass {
+      name,
+      reads: reads.to_vec(),
+      writes: writes.to_vec(),
+      run: Box::new(run),
+    });
+    self.order = self.sort_passes();
+  }
+
+  // Whether pass `a` must run before pass `b`
+  fn runs_before(&self, a: usize, b: usize) -> bool {
+    let (first, second) = (&self.passes[a], &self.passes[b]);
+    let written_then_read = first
+      .writes
+      .iter()
+      .any(|resource| second.reads.contains(resource));
+    let written_twice = a < b
+      && first
+        .writes
+        .iter()
+        .any(|resource| second.writes.contains(resource));
+    written_then_read || written_twice
+  }
+
+  // Kahn's algorithm, picking the earliest added pass among the ready ones
+  fn sort_passes(&self) -> Vec<usize> {
+    let count = self.passes.len();
+    let mut remaining_dependencies: Vec<usize> = (0..count)
+      .map(|pass| {
+        (0..count)
+          .filter(|other| *other != pass && self.runs_before(*other, pass))
+          .count()
+      })
+      .collect();
+    let mut done = vec![false; count];
+    let mut order = Vec::with_capacity(count);
+    while order.len() < count {
+      let next = (0..count)
+        .find(|pass| !done[*pass] && remaining_dependencies[*pass] == 0)
+        .unwrap_or_else(|| {
+          panic!(
+            "render graph has a dependency cycle around pass {}",
+            self.passes[(0..count).find(|pass| !done[*pass]).unwrap()].name
+          )
+        });
+      done[next] = true;
+      order.push(next);
+      (0..count)
+        .filter(|pass| !done[*pass] && self.runs_before(next, *pass))
+        .for_each(|pass| remaining_dependencies[pass] -= 1);
+    }
+    order
+  }
+
+  // Names of the passes, in the order they run
+  pub fn pass_names(&self) -> Vec<&'static str> {
+    self
+      .order
+      .iter()
+      .map(|index| self.passes[*index].name)
+      .collect()
+  }
+
+  pub fn resize(&mut self, width: u32, height: u32) {
+    self.width = width;
+    self.height = height;
+    self.resources.iter_mut().for_each(|resource| {
+      if let Resource::Texture { allocated, .. } = resource {
+        *allocated = None;
+      }
+    });
+  }
+
+  // Allocates the textures which don't exist yet
+  pub fn prepare(&mut self, device: &Device) {
+    let (width, height) = (self.width, self.height);
+    self.resources.iter_mut().for_each(|resource| {
+      if let Resource::Texture {
+        desc,
+        allocated: allocated @ None,
+      } = resource
+      {
+        let texture = device.create_texture(&TextureDescriptor {
+          label: Some(desc.label),
+          size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+          },
+          mip_level_count: 1,
+          sample_count: desc.sample_count,
+          dimension: TextureDimension::D2,
+          format: desc.format,
+          usage: desc.usage,
+          view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        *allocated = Some((texture, view));
+      }
+    });
+  }
+
+  pub fn execute(
+    &self,
+    encoder: &mut CommandEncoder,
+    imports: &[(&str, &TextureView)],
+    renderer: &BloomRenderer,
+    meshes: &[&Mesh],
+    timer: Option<&GpuTimer>,
+  ) {
+    let resources = PassResources {
+      views: self
+        .resources
+        .iter()
+        .map(|resource| match resource {
+          Resource::Texture { allocated, .. } => {
+            allocated.as_ref().map(|(_, view)| view)
+          }
+          Resource::Imported { name } => imports
+            .iter()
+            .find(|(import, _)| import == name)
+            .map(|(_, view)| *view),
+        })
+        .collect(),
+    };
+    let mut context = PassContext {
+      encoder,
+      resources: &resources,
+      renderer,
+      meshes,
+    };
+    // With a timer, each pass is surrounded by a pair of timestamps
+    if timer.is_some() {
+      assert!(
+        self.order.len() as u32 <= GpuTimer::MAX_PASSES,
+        "render graph has more passes than the GPU timer can measure"
+      );
+    }
+    self.order.iter().enumerate().for_each(|(position, index)| {
+      let query = position as u32 * 2;
+      if let Some(timer) = timer {
+        context.encoder.write_timestamp(timer.query_set(), query);
+      }
+      (self.passes[*index].run)(&mut context);
+      if let Some(timer) = timer {
+        context
+          .encoder
+          .write_timestamp(timer.query_set(), query + 1);
+      }
+    });
+  }
+}
@@ -0,0 +1,76 @@
+use std::{
+  path::PathBuf,
+  time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::*;
+use wgpu::{
+  Device, ErrorFilter, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+};
+
+// Development mode where the block and sky shaders are read from disk and
+// rebuilt whenever they change, instead of only using the copies embedded in
+// the binary. Enabled by pointing the BLOOM_SHADER_DIR environment variable
+// at the shader directory, usually src/engine/shaders
+pub struct ShaderWatcher {
+  directory: PathBuf,
+  modified: Vec<(&'static str, Option<SystemTime>)>,
+  last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+  pub const SHADERS: [&'static str; 2] = ["default.wgsl", "sky.wgsl"];
+  const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+  pub fn from_env() -> Option<Self> {
+    let directory = PathBuf::from(std::env::var_os("BLOOM_SHADER_DIR")?);
+    Some(Self {
+      directory,
+      // Nothing is known about the files yet, so the first poll loads them
+      // all and replaces the embedded shaders, which may be out of date
+      modified: Self::SHADERS.iter().map(|name| (*name, None)).collect(),
+      last_poll: None,
+    })
+  }
+
+  // Shaders modified since the last call. The files are only checked every
+  // so often, so calling this every frame is fine
+  pub fn changed(&mut self) -> Vec<&'static str> {
+    if self
+      .last_poll
+      .is_some_and(|last_poll| last_poll.elapsed() < Self::POLL_INTERVAL)
+    {
+      return vec![];
+    }
+    self.last_poll = Some(Instant::now());
+    let directory = &self.directory;
+    self
+      .modified
+      .iter_mut()
+      .filter_map(|(name, modified)| {
+        let current = std::fs::metadata(directory.join(*name))
+          .and_then(|metadata| metadata.modified())
+          .ok();
+        if current.is_none() || current == *modified {
+          return None;
+        }
+        *modified = current;
+        Some(*name)
+      })
+      .collect()
+  }
+
+  // Reads and compiles the shader, reporting errors instead of panicking
+  pub fn load(&self, name: &str, device: &Device) -> Result<ShaderModule> {
+    let source = std::fs::read_to_string(self.directory.join(name))?;
+    device.push_error_scope(ErrorFilter::Validation);
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+      label: Some(name),
+      source: ShaderSource::Wgsl(source.into()),
+    });
+    match pollster::block_on(device.pop_error_scope()) {
+      Some(error) => Err(anyhow!("{}", error)),
+      None => Ok(module),
+    }
+  }
+}
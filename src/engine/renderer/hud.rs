@@ -0,0 +1,397 @@
+use wgpu::{
+  include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry,
+  BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource,
+  BindingType, BlendState, Buffer, BufferAddress, BufferDescriptor,
+  BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, FilterMode,
+  FragmentState, FrontFace, ImageCopyTexture, ImageDataLayout,
+  MultisampleState, Origin3d, PipelineLayoutDescriptor, PolygonMode,
+  PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline,
+  RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor,
+  ShaderStages, TextureAspect, TextureDescriptor, TextureDimension,
+  TextureFormat, TextureSampleType, TextureUsages, TextureView,
+  TextureViewDescriptor, TextureViewDimension, VertexBufferLayout, VertexState,
+  VertexStepMode,
+};
+
+use super::font::{glyph_index, GLYPHS, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HudVertex {
+  position: [f32; 2], // Clip space
+  tex_coords: [f32; 2],
+  color: [f32; 4],
+}
+
+impl HudVertex {
+  const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    0 => Float32x2,
+    1 => Float32x2,
+    2 => Float32x4
+  ];
+  pub fn layout() -> VertexBufferLayout<'static> {
+    use std::mem;
+
+    VertexBufferLayout {
+      array_stride: mem::size_of::<Self>() as BufferAddress,
+      step_mode: VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBS,
+    }
+  }
+}
+
+// 2D overlay drawn over the final image, made of text and filled rectangles.
+// Like the debug draw shapes, everything is added again every frame.
+// Positions and sizes are in pixels from the top left corner of the surface
+pub struct Hud {
+  width: u32,
+  height: u32,
+
+  vertices: Vec<HudVertex>,
+  buffer: Buffer,
+  capacity: usize, // In vertices
+  vertex_count: u32,
+
+  bind_group: BindGroup,
+  pipeline: RenderPipeline,
+}
+
+impl Hud {
+  const INITIAL_CAPACITY: usize = 1024;
+  // Glyphs are laid out in cells with a pixel of spacing, so that sampling
+  // never bleeds into the neighbouring glyphs
+  const ATLAS_COLUMNS: u32 = 16;
+  const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+  const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+
+  pub fn new(
+    width: u32,
+    height: u32,
+    surface_format: TextureFormat,
+    device: &Device,
+    queue: &Queue,
+  ) -> Self {
+    let bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("hud_bind_group_layout"),
+        entries: &Self::BIND_LAYOUT_ENTRIES,
+      });
+
+    let shader =
+      device.create_shader_module(include_wgsl!("../shaders/hud.wgsl"));
+    let pipeline_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("hud_pipeline_layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("render_pipeline:hud"),
+      layout: Some(&pipeline_layout),
+      vertex: VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[HudVertex::layout()],
+      },
+      fragment: Some(FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(ColorTargetState {
+          format: surface_format,
+          blend: Some(BlendState::ALPHA_BLENDING),
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: None,
+      multisample: MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    let atlas_view = Self::create_atlas(device, queue);
+    let sampler = device.create_sampler(&SamplerDescriptor {
+      label: Some("hud_sampler"),
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mag_filter: FilterMode::Nearest,
+      min_filter: FilterMode::Nearest,
+      mipmap_filter: FilterMode::Nearest,
+      ..Default::default()
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("hud_bind_group"),
+      layout: &bind_group_layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: BindingResource::TextureView(&atlas_view),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: BindingResource::Sampler(&sampler),
+        },
+      ],
+    });
+
+    Self {
+      width,
+      height,
+
+      vertices: vec![],
+      buffer: Self::create_buffer(Self::INITIAL_CAPACITY, device),
+      capacity: Self::INITIAL_CAPACITY,
+      vertex_count: 0,
+
+      bind_group,
+      pipeline,
+    }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 2] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Sampler(SamplerBindingType::Filtering),
+      count: None,
+    },
+  ];
+
+  fn atlas_size() -> (u32, u32) {
+    // The first cell is fully covered and used to fill rectangles
+    let cells = GLYPHS.len() as u32 + 1;
+    (
+      Self::ATLAS_COLUMNS * Self::CELL_WIDTH,
+      cells.div_ceil(Self::ATLAS_COLUMNS) * Self::CELL_HEIGHT,
+    )
+  }
+
+  // Top left texel of the cell
+  fn cell_origin(cell: u32) -> (u32, u32) {
+    (
+      cell % Self::ATLAS_COLUMNS * Self::CELL_WIDTH,
+      cell / Self::ATLAS_COLUMNS * Self::CELL_HEIGHT,
+    )
+  }
+
+  // Single channel coverage of every glyph, rasterized from the font
+  fn create_atlas(device: &Device, queue: &Queue) -> TextureView {
+    let (width, height) = Self::atlas_size();
+    let mut texels = vec![0u8; (width * height) as usize];
+    let mut set_texel = |x: u32, y: u32| {
+      texels[(y * width + x) as usize] = 255;
+    };
+    let solid = [(1 << GLYPH_WIDTH) - 1; GLYPH_HEIGHT as usize];
+    std::iter::once(&solid)
+      .chain(GLYPHS.iter().map(|(_, rows)| rows))
+      .enumerate()
+      .for_each(|(cell, rows)| {
+        let (cell_x, cell_y) = Self::cell_origin(cell as u32);
+        rows.iter().enumerate().for_each(|(row, bits)| {
+          (0..GLYPH_WIDTH)
+            .filter(|column| bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0)
+            .for_each(|column| set_texel(cell_x + column, cell_y + row as u32));
+        });
+      });
+
+    let size = Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+      label: Some("hud_atlas_texture"),
+      size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: TextureDimension::D2,
+      format: TextureFormat::R8Unorm,
+      usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+    queue.write_texture(
+      ImageCopyTexture {
+        texture: &texture,
+        mip_level: 0,
+        origin: Origin3d::ZERO,
+        aspect: TextureAspect::All,
+      },
+      &texels,
+      ImageDataLayout {
+        offset: 0,
+        bytes_per_row: Some(width),
+        rows_per_image: Some(height),
+      },
+      size,
+    );
+    texture.create_view(&TextureViewDescriptor::default())
+  }
+
+  fn create_buffer(capacity: usize, device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some("hud_buffer"),
+      size: (capacity * std::mem::size_of::<HudVertex>()) as u64,
+      usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  // Quad covering the given pixels, textured with the given atlas texels
+  fn quad(
+    &mut self,
+    (x, y, width, height): (f32, f32, f32, f32),
+    (u, v, u_size, v_size): (f32, f32, f32, f32),
+    color: [f32; 4],
+  ) {
+    let (atlas_width, atlas_height) = Self::atlas_size();
+    let vertex = |dx: f32, dy: f32| HudVertex {
+      position: [
+        (x + dx * width) / self.width as f32 * 2.0 - 1.0,
+        1.0 - (y + dy * height) / self.height as f32 * 2.0,
+      ],
+      tex_coords: [
+        (u + dx * u_size) / atlas_width as f32,
+        (v + dy * v_size) / atlas_height as f32,
+      ],
+      color,
+    };
+    let corners = [
+      vertex(0.0, 0.0),
+      vertex(0.0, 1.0),
+      vertex(1.0, 1.0),
+      vertex(1.0, 0.0),
+    ];
+    [0, 1, 2, 0, 2, 3]
+      .into_iter()
+      .for_each(|corner| self.vertices.push(corners[corner]));
+  }
+
+  pub fn rect(
+    &mut self,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    color: [f32; 4],
+  ) {
+    // Middle of the solid cell, so the whole rectangle is covered
+    let (u, v) = Self::cell_origin(0);
+    let texel = (u as f32 + 2.5, v as f32 + 3.5, 0.0, 0.0);
+    self.quad((x, y, width, height), texel, color);
+  }
+
+  // Text starting at its top left corner, with each font pixel drawn as a
+  // `scale` sized square. Lines are separated by '\n'
+  pub fn text(
+    &mut self,
+    x: f32,
+    y: f32,
+    text: &str,
+    scale: f32,
+    color: [f32; 4],
+  ) {
+    let (glyph_width, glyph_height) =
+      (GLYPH_WIDTH as f32 * scale, GLYPH_HEIGHT as f32 * scale);
+    text.lines().enumerate().for_each(|(line, characters)| {
+      let line_y = y + line as f32 * Self::line_height(scale);
+      characters
+        .chars()
+        .enumerate()
+        .for_each(|(column, character)| {
+          let Some(glyph) = glyph_index(character) else {
+            return;
+          };
+          let (u, v) = Self::cell_origin(glyph as u32 + 1);
+          self.quad(
+            (
+              x + column as f32 * Self::advance(scale),
+              line_y,
+              glyph_width,
+              glyph_height,
+            ),
+            (u as f32, v as f32, GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32),
+            color,
+          );
+        });
+    });
+  }
+
+  pub fn advance(scale: f32) -> f32 {
+    (GLYPH_WIDTH + 1) as f32 * scale
+  }
+
+  pub fn line_height(scale: f32) -> f32 {
+    (GLYPH_HEIGHT + 2) as f32 * scale
+  }
+
+  // Size in pixels of the text once drawn
+  pub fn text_size(text: &str, scale: f32) -> (f32, f32) {
+    let columns = text.lines().map(|line| line.chars().count()).max();
+    let lines = text.lines().count();
+    (
+      columns.unwrap_or(0) as f32 * Self::advance(scale),
+      lines as f32 * Self::line_height(scale),
+    )
+  }
+
+  // Copies the quads collected so far to the GPU, growing the buffer when
+  // they don't fit
+  pub fn upload(&mut self, device: &Device, queue: &Queue) {
+    if self.vertices.len() > self.capacity {
+      self.capacity = self.vertices.len().next_power_of_two();
+      self.buffer = Self::create_buffer(self.capacity, device);
+    }
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+    self.vertex_count = self.vertices.len() as u32;
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.vertex_count == 0
+  }
+
+  pub fn clear(&mut self) {
+    self.vertices.clear();
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, &self.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+
+  pub fn resize(&mut self, width: u32, height: u32) {
+    self.width = width;
+    self.height = height;
+  }
+}
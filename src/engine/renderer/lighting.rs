@@ -0,0 +1,303 @@
+use cgmath::{
+  ortho, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform,
+  Vector3,
+};
+use wgpu::{
+  AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+  BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource,
+  BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
+  CompareFunction, Device, Extent3d, FilterMode, Queue, SamplerBindingType,
+  SamplerDescriptor, ShaderStages, TextureDescriptor, TextureDimension,
+  TextureFormat, TextureSampleType, TextureUsages, TextureView,
+  TextureViewDescriptor, TextureViewDimension,
+};
+
+use crate::engine::{camera::Camera, math::OPENGL_TO_WGPU_MATRIX};
+
+pub const SHADOW_CASCADE_COUNT: usize = 3;
+const SHADOW_MAP_SIZE: u32 = 2048;
+// Blend between logarithmic (1.0) and uniform (0.0) cascade splits
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+// How far behind a cascade shadow casters are still taken into account
+const SHADOW_CASTER_MARGIN: f32 = 64.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+  cascade_matrices: [[[f32; 4]; 4]; SHADOW_CASCADE_COUNT],
+  cascade_splits: [f32; 4], // View distance at which each cascade ends
+  camera_position: [f32; 4],
+  view_direction: [f32; 4],
+  sun_direction: [f32; 4], // Points towards the sun
+  sun_intensity: f32,
+  ambient: f32,
+  shadows_enabled: u32,
+  _padding: u32,
+}
+
+pub struct ShadowCascade {
+  pub view: TextureView,
+  pub bind_group: BindGroup,
+  buffer: Buffer,
+}
+
+// Sun light and its cascaded shadow map
+pub struct Lighting {
+  sun_direction: Vector3<f32>,
+  sun_intensity: f32,
+  ambient: f32,
+
+  pub cascades: Vec<ShadowCascade>,
+
+  buffer: Buffer,
+  pub bind_group: BindGroup,
+}
+
+impl Lighting {
+  // The shadow texture is only kept alive by its views and the bind group
+  pub fn new(
+    lighting_bind_group_layout: &BindGroupLayout,
+    cascade_bind_group_layout: &BindGroupLayout,
+    device: &Device,
+  ) -> Self {
+    let shadow_texture = device.create_texture(&TextureDescriptor {
+      label: Some("shadow_texture"),
+      size: Extent3d {
+        width: SHADOW_MAP_SIZE,
+        height: SHADOW_MAP_SIZE,
+        depth_or_array_layers: SHADOW_CASCADE_COUNT as u32,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: TextureDimension::D2,
+      format: TextureFormat::Depth32Float,
+      usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+    });
+    let shadow_view = shadow_texture.create_view(&TextureViewDescriptor {
+      label: Some("shadow_view"),
+      dimension: Some(TextureViewDimension::D2Array),
+      ..Default::default()
+    });
+    let shadow_sampler = device.create_sampler(&SamplerDescriptor {
+      label: Some("shadow_sampler"),
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Nearest,
+      compare: Some(CompareFunction::LessEqual),
+      ..Default::default()
+    });
+
+    let cascades = (0..SHADOW_CASCADE_COUNT)
+      .map(|i| {
+        let view = shadow_texture.create_view(&TextureViewDescriptor {
+          label: Some("shadow_cascade_view"),
+          dimension: Some(TextureViewDimension::D2),
+          base_array_layer: i as u32,
+          array_layer_count: Some(1),
+          ..Default::default()
+        });
+        let buffer = device.create_buffer(&BufferDescriptor {
+          label: Some("shadow_cascade_buffer"),
+          size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+          usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+          mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+          label: Some("shadow_cascade_bind_group"),
+          layout: cascade_bind_group_layout,
+          entries: &[BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+          }],
+        });
+        ShadowCascade {
+          view,
+          bind_group,
+          buffer,
+        }
+      })
+      .collect();
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+      label: Some("lighting_buffer"),
+      size: std::mem::size_of::<LightingUniform>() as u64,
+      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("lighting_bind_group"),
+      layout: lighting_bind_group_layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: buffer.as_entire_binding(),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: BindingResource::TextureView(&shadow_view),
+        },
+        BindGroupEntry {
+          binding: 2,
+          resource: BindingResource::Sampler(&shadow_sampler),
+        },
+      ],
+    });
+
+    Self {
+      sun_direction: Vector3::new(0.3, 1.0, 0.5).normalize(),
+      sun_intensity: 1.0,
+      ambient: 0.5,
+
+      cascades,
+
+      buffer,
+      bind_group,
+    }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 3] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::VERTEX_FRAGMENT,
+      ty: BindingType::Buffer {
+        ty: BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2Array,
+        sample_type: TextureSampleType::Depth,
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 2,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Sampler(SamplerBindingType::Comparison),
+      count: None,
+    },
+  ];
+  pub fn bind_group_layout_desc(
+    label: Option<&str>,
+  ) -> BindGroupLayoutDescriptor<'_> {
+    BindGroupLayoutDescriptor {
+      label,
+      entries: &Self::BIND_LAYOUT_ENTRIES,
+    }
+  }
+
+  pub fn sun_direction(&self) -> Vector3<f32> {
+    self.sun_direction
+  }
+  pub fn set_sun_direction(&mut self, direction: Vector3<f32>) {
+    self.sun_direction = direction.normalize();
+  }
+
+  pub fn sun_intensity(&self) -> f32 {
+    self.sun_intensity
+  }
+  pub fn set_sun_intensity(&mut self, intensity: f32) {
+    self.sun_intensity = intensity;
+  }
+
+  pub fn ambient(&self) -> f32 {
+    self.ambient
+  }
+  pub fn set_ambient(&mut self, ambient: f32) {
+    self.ambient = ambient;
+  }
+
+  // Fits each cascade around its slice of the camera frustum and uploads the
+  // resulting light matrices
+  pub fn update(&self, camera: &Camera, shadows_enabled: bool, queue: &Queue) {
+    let splits = Self::cascade_splits(camera.znear(), camera.zfar());
+    let mut cascade_matrices = [[[0.0; 4]; 4]; SHADOW_CASCADE_COUNT];
+    let mut cascade_splits = [0.0; 4];
+    let mut slice_near = camera.znear();
+    self.cascades.iter().zip(splits).enumerate().for_each(
+      |(i, (cascade, slice_far))| {
+        let corners = camera.frustum_corners(slice_near, slice_far);
+        let matrix: [[f32; 4]; 4] = self.cascade_matrix(&corners).into();
+        queue.write_buffer(&cascade.buffer, 0, bytemuck::cast_slice(&[matrix]));
+        cascade_matrices[i] = matrix;
+        cascade_splits[i] = slice_far;
+        slice_near = slice_far;
+      },
+    );
+
+    let position = camera.position();
+    let view_direction = camera.view_direction();
+    let uniform = LightingUniform {
+      cascade_matrices,
+      cascade_splits,
+      camera_position: [position.x, position.y, position.z, 1.0],
+      view_direction: view_direction.extend(0.0).into(),
+      sun_direction: self.sun_direction.extend(0.0).into(),
+      sun_intensity: self.sun_intensity,
+      ambient: self.ambient,
+      shadows_enabled: shadows_enabled as u32,
+      _padding: 0,
+    };
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn cascade_splits(znear: f32, zfar: f32) -> [f32; SHADOW_CASCADE_COUNT] {
+    let mut splits = [zfar; SHADOW_CASCADE_COUNT];
+    splits.iter_mut().enumerate().for_each(|(i, split)| {
+      let fraction = (i + 1) as f32 / SHADOW_CASCADE_COUNT as f32;
+      let logarithmic = znear * (zfar / znear).powf(fraction);
+      let uniform = znear + (zfar - znear) * fraction;
+      *split = CASCADE_SPLIT_LAMBDA * logarithmic
+        + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+    });
+    splits
+  }
+
+  fn cascade_matrix(&self, corners: &[Point3<f32>; 8]) -> Matrix4<f32> {
+    let light_direction = -self.sun_direction;
+    let up = if light_direction.y.abs() > 0.99 {
+      Vector3::unit_z()
+    } else {
+      Vector3::unit_y()
+    };
+
+    // Fitting a sphere rather than a box keeps the cascade size constant
+    // while the camera rotates, which avoids shimmering shadow edges
+    let center = Point3::centroid(corners);
+    let radius = corners
+      .iter()
+      .map(|corner| (corner - center).magnitude())
+      .fold(0.0, f32::max)
+      .ceil();
+
+    // Move the center in whole shadow map texels, for the same reason
+    let texel_size = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+    let rotation = Matrix4::look_to_rh(Point3::origin(), light_direction, up);
+    let mut light_center = rotation.transform_point(center);
+    light_center.x = (light_center.x / texel_size).floor() * texel_size;
+    light_center.y = (light_center.y / texel_size).floor() * texel_size;
+    let center = rotation.invert().unwrap().transform_point(light_center);
+
+    let eye = center - light_direction * (radius + SHADOW_CASTER_MARGIN);
+    let view = Matrix4::look_to_rh(eye, light_direction, up);
+    let proj = ortho(
+      -radius,
+      radius,
+      -radius,
+      radius,
+      0.0,
+      2.0 * radius + SHADOW_CASTER_MARGIN,
+    );
+    OPENGL_TO_WGPU_MATRIX * proj * view
+  }
+}
@@ -0,0 +1,393 @@
+use wgpu::{
+  include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry,
+  BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+  BindingResource, BindingType, BlendComponent, BlendFactor, BlendOperation,
+  BlendState, Color, ColorTargetState, ColorWrites, DepthBiasState,
+  DepthStencilState, Device, Extent3d, Face, FragmentState, FrontFace, LoadOp,
+  MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor,
+  PolygonMode, PrimitiveState, PrimitiveTopology, RenderPassColorAttachment,
+  RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderStages,
+  StencilState, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+  TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+  TextureViewDimension, VertexState,
+};
+
+use crate::engine::{camera::DepthRange, model::Vertex};
+
+pub const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+type Target = (Texture, TextureView);
+
+// Weighted blended order independent transparency. Transparent faces are
+// accumulated in any order into two offscreen targets, which are then
+// composited over the opaque scene. With MSAA, faces are accumulated into
+// multisampled targets which resolve into the ones the composite reads
+pub struct Oit {
+  sample_count: u32,
+  multisampled_accumulation: Option<Target>,
+  multisampled_revealage: Option<Target>,
+
+  accumulation_texture: Texture,
+  pub accumulation_view: TextureView,
+  revealage_texture: Texture,
+  pub revealage_view: TextureView,
+
+  composite_bind_group_layout: BindGroupLayout,
+  pub composite_bind_group: BindGroup,
+
+  pub accumulate_pipeline: RenderPipeline,
+  pub composite_pipeline: RenderPipeline,
+}
+
+impl Oit {
+  pub fn new(
+    width: u32,
+    height: u32,
+    block_pipeline_layout: &PipelineLayout,
+    block_shader: &ShaderModule,
+    surface_format: TextureFormat,
+    sample_count: u32,
+    depth_range: DepthRange,
+    device: &Device,
+  ) -> Self {
+    let composite_bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("oit_composite_bind_group_layout"),
+        entries: &Self::BIND_LAYOUT_ENTRIES,
+      });
+
+    let accumulate_pipeline =
+      device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("render_pipeline:oit_accumulate"),
+        layout: Some(block_pipeline_layout),
+        vertex: VertexState {
+          module: block_shader,
+          entry_point: "vs_main",
+          buffers: &[Vertex::layout()],
+        },
+        fragment: Some(FragmentState {
+          module: block_shader,
+          entry_point: "fs_oit",
+          targets: &[
+            Some(ColorTargetState {
+              format: ACCUMULATION_FORMAT,
+              blend: Some(BlendState {
+                color: BlendComponent {
+                  src_factor: BlendFactor::One,
+                  dst_factor: BlendFactor::One,
+                  operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                  src_factor: BlendFactor::One,
+                  dst_factor: BlendFactor::One,
+                  operation: BlendOperation::Add,
+                },
+              }),
+              write_mask: ColorWrites::ALL,
+            }),
+            // Each fragment multiplies the revealage by its transparency
+            Some(ColorTargetState {
+              format: REVEALAGE_FORMAT,
+              blend: Some(BlendState {
+                color: BlendComponent {
+                  src_factor: BlendFactor::Zero,
+                  dst_factor: BlendFactor::OneMinusSrc,
+                  operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                  src_factor: BlendFactor::Zero,
+                  dst_factor: BlendFactor::OneMinusSrc,
+                  operation: BlendOperation::Add,
+                },
+              }),
+              write_mask: ColorWrites::ALL,
+            }),
+          ],
+        }),
+        primitive: PrimitiveState {
+          topology: PrimitiveTopology::TriangleList,
+          strip_index_format: None,
+          front_face: FrontFace::Ccw,
+          cull_mode: Some(Face::Back),
+          polygon_mode: PolygonMode::Fill,
+          unclipped_depth: false,
+          conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+          format: TextureFormat::Depth32Float,
+          depth_write_enabled: false,
+          depth_compare: depth_range.compare(),
+          stencil: StencilState::default(),
+          bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+          count: sample_count,
+          mask: !0,
+          alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+      });
+
+    let composite_shader = device
+      .create_shader_module(include_wgsl!("../shaders/oit_composite.wgsl"));
+    let composite_pipeline_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("oit_composite_pipeline_layout"),
+        bind_group_layouts: &[&composite_bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let composite_pipeline =
+      device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("render_pipeline:oit_composite"),
+        layout: Some(&composite_pipeline_layout),
+        vertex: VertexState {
+          module: &composite_shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        fragment: Some(FragmentState {
+          module: &composite_shader,
+          entry_point: "fs_main",
+          targets: &[Some(ColorTargetState {
+            format: surface_format,
+            blend: Some(BlendState::ALPHA_BLENDING),
+            write_mask: ColorWrites::ALL,
+          })],
+        }),
+        primitive: PrimitiveState {
+          topology: PrimitiveTopology::TriangleList,
+          strip_index_format: None,
+          front_face: FrontFace::Ccw,
+          cull_mode: None,
+          polygon_mode: PolygonMode::Fill,
+          unclipped_depth: false,
+          conservative: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState {
+          count: 1,
+          mask: !0,
+          alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+      });
+
+    let (accumulation_texture, accumulation_view) = Self::create_target(
+      "oit_accumulation_texture",
+      ACCUMULATION_FORMAT,
+      width,
+      height,
+      1,
+      device,
+    );
+    let (revealage_texture, revealage_view) = Self::create_target(
+      "oit_revealage_texture",
+      REVEALAGE_FORMAT,
+      width,
+      height,
+      1,
+      device,
+    );
+    let (multisampled_accumulation, multisampled_revealage) =
+      Self::create_multisampled_targets(width, height, sample_count, device);
+    let composite_bind_group = Self::create_composite_bind_group(
+      &composite_bind_group_layout,
+      &accumulation_view,
+      &revealage_view,
+      device,
+    );
+
+    Self {
+      sample_count,
+      multisampled_accumulation,
+      multisampled_revealage,
+
+      accumulation_texture,
+      accumulation_view,
+      revealage_texture,
+      revealage_view,
+
+      composite_bind_group_layout,
+      composite_bind_group,
+
+      accumulate_pipeline,
+      composite_pipeline,
+    }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 2] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: false },
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: false },
+      },
+      count: None,
+    },
+  ];
+
+  fn create_target(
+    label: &str,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    device: &Device,
+  ) -> Target {
+    let texture = device.create_texture(&TextureDescriptor {
+      label: Some(label),
+      size: Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count,
+      dimension: TextureDimension::D2,
+      format,
+      usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    (texture, view)
+  }
+
+  fn create_multisampled_targets(
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    device: &Device,
+  ) -> (Option<Target>, Option<Target>) {
+    if sample_count == 1 {
+      return (None, None);
+    }
+    (
+      Some(Self::create_target(
+        "oit_multisampled_accumulation_texture",
+        ACCUMULATION_FORMAT,
+        width,
+        height,
+        sample_count,
+        device,
+      )),
+      Some(Self::create_target(
+        "oit_multisampled_revealage_texture",
+        REVEALAGE_FORMAT,
+        width,
+        height,
+        sample_count,
+        device,
+      )),
+    )
+  }
+
+  // Attachments of the accumulate pass, cleared to no colour and full
+  // revealage
+  pub fn color_attachments(
+    &self,
+  ) -> [Option<RenderPassColorAttachment<'_>>; 2] {
+    [
+      Self::color_attachment(
+        &self.multisampled_accumulation,
+        &self.accumulation_view,
+        Color::TRANSPARENT,
+      ),
+      Self::color_attachment(
+        &self.multisampled_revealage,
+        &self.revealage_view,
+        Color::WHITE,
+      ),
+    ]
+  }
+
+  fn color_attachment<'a>(
+    multisampled: &'a Option<Target>,
+    resolved: &'a TextureView,
+    clear: Color,
+  ) -> Option<RenderPassColorAttachment<'a>> {
+    let (view, resolve_target) = match multisampled {
+      Some((_, view)) => (view, Some(resolved)),
+      None => (resolved, None),
+    };
+    Some(RenderPassColorAttachment {
+      view,
+      resolve_target,
+      ops: Operations {
+        load: LoadOp::Clear(clear),
+        store: true,
+      },
+    })
+  }
+
+  fn create_composite_bind_group(
+    layout: &BindGroupLayout,
+    accumulation_view: &TextureView,
+    revealage_view: &TextureView,
+    device: &Device,
+  ) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+      label: Some("oit_composite_bind_group"),
+      layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: BindingResource::TextureView(accumulation_view),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: BindingResource::TextureView(revealage_view),
+        },
+      ],
+    })
+  }
+
+  // The targets must always match the size of the depth texture
+  pub fn resize(&mut self, width: u32, height: u32, device: &Device) {
+    let (accumulation_texture, accumulation_view) = Self::create_target(
+      "oit_accumulation_texture",
+      ACCUMULATION_FORMAT,
+      width,
+      height,
+      1,
+      device,
+    );
+    let (revealage_texture, revealage_view) = Self::create_target(
+      "oit_revealage_texture",
+      REVEALAGE_FORMAT,
+      width,
+      height,
+      1,
+      device,
+    );
+    (self.multisampled_accumulation, self.multisampled_revealage) =
+      Self::create_multisampled_targets(
+        width,
+        height,
+        self.sample_count,
+        device,
+      );
+    self.composite_bind_group = Self::create_composite_bind_group(
+      &self.composite_bind_group_layout,
+      &accumulation_view,
+      &revealage_view,
+      device,
+    );
+    self.accumulation_texture = accumulation_texture;
+    self.accumulation_view = accumulation_view;
+    self.revealage_texture = revealage_texture;
+    self.revealage_view = revealage_view;
+  }
+}
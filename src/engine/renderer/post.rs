@@ -0,0 +1,746 @@
+use anyhow::{ensure, Result};
+use image::GenericImageView;
+use wgpu::{
+  include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry,
+  BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+  BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor,
+  BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoder, Device,
+  Extent3d, FilterMode, FragmentState, FrontFace, ImageCopyTexture,
+  ImageDataLayout, LoadOp, MultisampleState, Operations, Origin3d,
+  PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+  PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+  RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+  SamplerDescriptor, ShaderModule, ShaderStages, Texture, TextureAspect,
+  TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+  TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+  VertexState,
+};
+
+// The scene is rendered and post-processed in linear HDR
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+  // Maps HDR colours into the displayable range with a filmic curve
+  ToneMapping,
+  Gamma,
+  // Bright areas bleed light onto their surroundings
+  Bloom,
+  Vignette,
+  // Remaps colours through a 3D lookup table
+  ColorGrading,
+}
+
+pub struct PostSettings {
+  pub effects: Vec<PostEffect>, // Applied in order, missing ones are skipped
+  pub exposure: f32,
+  pub gamma: f32, // Of the display, 2.2 leaves sRGB surfaces unchanged
+  pub bloom_threshold: f32, // Brightness above which colours bloom
+  pub bloom_intensity: f32,
+  pub vignette_strength: f32,
+  pub vignette_radius: f32, // 0.0 at the centre, 1.0 at the corners
+  pub color_grading_strength: f32, // Blend between ungraded and graded
+}
+
+impl Default for PostSettings {
+  fn default() -> Self {
+    Self {
+      effects: vec![
+        PostEffect::Bloom,
+        PostEffect::ToneMapping,
+        PostEffect::ColorGrading,
+        PostEffect::Vignette,
+        PostEffect::Gamma,
+      ],
+      exposure: 1.0,
+      gamma: 2.2,
+      bloom_threshold: 1.0,
+      bloom_intensity: 0.3,
+      vignette_strength: 0.25,
+      vignette_radius: 0.5,
+      color_grading_strength: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+  exposure: f32,
+  gamma: f32,
+  output_srgb: u32,
+  bloom_threshold: f32,
+  bloom_intensity: f32,
+  vignette_strength: f32,
+  vignette_radius: f32,
+  color_grading_strength: f32,
+}
+
+// A texture effects draw into, with the bind group reading it back
+struct PostTarget {
+  texture: Texture,
+  view: TextureView,
+  bind_group: BindGroup,
+}
+
+// Chain of fullscreen passes between the HDR scene and the surface. Effects
+// ping-pong between two HDR targets, the last one is then copied into the
+// output
+pub struct PostProcessing {
+  output_srgb: bool,
+
+  buffer: Buffer,
+  sampler: Sampler,
+
+  input_bind_group_layout: BindGroupLayout,
+  texture_bind_group_layout: BindGroupLayout,
+  lut_bind_group_layout: BindGroupLayout,
+
+  targets: [PostTarget; 2], // The scene is rendered into the first one
+  bloom_targets: [PostTarget; 2], // Half resolution
+  bloom_bind_group: BindGroup,
+
+  lut_texture: Texture,
+  lut_bind_group: BindGroup,
+
+  tone_mapping_pipeline: RenderPipeline,
+  gamma_pipeline: RenderPipeline,
+  vignette_pipeline: RenderPipeline,
+  color_grading_pipeline: RenderPipeline,
+  bloom_prefilter_pipeline: RenderPipeline,
+  bloom_blur_horizontal_pipeline: RenderPipeline,
+  bloom_blur_vertical_pipeline: RenderPipeline,
+  bloom_composite_pipeline: RenderPipeline,
+  blit_pipeline: RenderPipeline,
+}
+
+impl PostProcessing {
+  pub fn new(
+    width: u32,
+    height: u32,
+    output_format: TextureFormat,
+    device: &Device,
+    queue: &Queue,
+  ) -> Self {
+    let buffer = device.create_buffer(&BufferDescriptor {
+      label: Some("post_buffer"),
+      size: std::mem::size_of::<PostUniform>() as u64,
+      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let sampler = device.create_sampler(&SamplerDescriptor {
+      label: Some("post_sampler"),
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mag_filter: FilterMode::Linear,
+      min_filter: FilterMode::Linear,
+      mipmap_filter: FilterMode::Nearest,
+      ..Default::default()
+    });
+
+    let input_bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("post_input_bind_group_layout"),
+        entries: &Self::INPUT_BIND_LAYOUT_ENTRIES,
+      });
+    let texture_bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("post_texture_bind_group_layout"),
+        entries: &[Self::texture_bind_layout_entry(TextureViewDimension::D2)],
+      });
+    let lut_bind_group_layout =
+      device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("post_lut_bind_group_layout"),
+        entries: &[Self::texture_bind_layout_entry(TextureViewDimension::D3)],
+      });
+
+    let single_input_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("post_pipeline_layout"),
+        bind_group_layouts: &[&input_bind_group_layout],
+        push_constant_ranges: &[],
+      });
+    let bloom_composite_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("post_bloom_composite_pipeline_layout"),
+        bind_group_layouts: &[
+          &input_bind_group_layout,
+          &texture_bind_group_layout,
+        ],
+        push_constant_ranges: &[],
+      });
+    let color_grading_layout =
+      device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("post_color_grading_pipeline_layout"),
+        bind_group_layouts: &[&input_bind_group_layout, &lut_bind_group_layout],
+        push_constant_ranges: &[],
+      });
+
+    let tone_mapping_shader = device
+      .create_shader_module(include_wgsl!("../shaders/post_tone_mapping.wgsl"));
+    let gamma_shader =
+      device.create_shader_module(include_wgsl!("../shaders/post_gamma.wgsl"));
+    let vignette_shader = device
+      .create_shader_module(include_wgsl!("../shaders/post_vignette.wgsl"));
+    let color_grading_shader = device.create_shader_module(include_wgsl!(
+      "../shaders/post_color_grading.wgsl"
+    ));
+    let bloom_shader =
+      device.create_shader_module(include_wgsl!("../shaders/post_bloom.wgsl"));
+    let blit_shader =
+      device.create_shader_module(include_wgsl!("../shaders/post_blit.wgsl"));
+
+    let tone_mapping_pipeline = Self::create_pipeline(
+      "render_pipeline:post_tone_mapping",
+      &single_input_layout,
+      &tone_mapping_shader,
+      "fs_main",
+      HDR_FORMAT,
+      device,
+    );
+    let gamma_pipeline = Self::create_pipeline(
+      "render_pipeline:post_gamma",
+      &single_input_layout,
+      &gamma_shader,
+      "fs_main",
+      HDR_FORMAT,
+      device,
+    );
+    let vignette_pipeline = Self::create_pipeline(
+      "render_pipeline:post_vignette",
+      &single_input_layout,
+      &vignette_shader,
+      "fs_main",
+      HDR_FORMAT,
+      device,
+    );
+    let color_grading_pipeline = Self::create_pipeline(
+      "render_pipeline:post_color_grading",
+      &color_grading_layout,
+      &color_grading_shader,
+      "fs_main",
+      HDR_FORMAT,
+      device,
+    );
+    let bloom_prefilter_pipeline = Self::create_pipeline(
+      "render_pipeline:post_bloom_prefilter",
+      &single_input_layout,
+      &bloom_shader,
+      "fs_prefilter",
+      HDR_FORMAT,
+      device,
+    );
+    let bloom_blur_horizontal_pipeline = Self::create_pipeline(
+      "render_pipeline:post_bloom_blur_horizontal",
+      &single_input_layout,
+      &bloom_shader,
+      "fs_blur_horizontal",
+      HDR_FORMAT,
+      device,
+    );
+    let bloom_blur_vertical_pipeline = Self::create_pipeline(
+      "render_pipeline:post_bloom_blur_vertical",
+      &single_input_layout,
+      &bloom_shader,
+      "fs_blur_vertical",
+      HDR_FORMAT,
+      device,
+    );
+    let bloom_composite_pipeline = Self::create_pipeline(
+      "render_pipeline:post_bloom_composite",
+      &bloom_composite_layout,
+      &bloom_shader,
+      "fs_composite",
+      HDR_FORMAT,
+      device,
+    );
+    let blit_pipeline = Self::create_pipeline(
+      "render_pipeline:post_blit",
+      &single_input_layout,
+      &blit_shader,
+      "fs_main",
+      output_format,
+      device,
+    );
+
+    let targets = Self::create_targets(
+      "post_texture",
+      width,
+      height,
+      &input_bind_group_layout,
+      &sampler,
+      &buffer,
+      device,
+    );
+    let bloom_targets = Self::create_targets(
+      "post_bloom_texture",
+      (width / 2).max(1),
+      (height / 2).max(1),
+      &input_bind_group_layout,
+      &sampler,
+      &buffer,
+      device,
+    );
+    let bloom_bind_group = Self::create_texture_bind_group(
+      "post_bloom_bind_group",
+      &texture_bind_group_layout,
+      &bloom_targets[0].view,
+      device,
+    );
+
+    let lut_texture = Self::create_lut_texture(IDENTITY_LUT_SIZE, device);
+    Self::write_lut(
+      &lut_texture,
+      IDENTITY_LUT_SIZE,
+      &Self::identity_lut(IDENTITY_LUT_SIZE),
+      queue,
+    );
+    let lut_bind_group = Self::create_texture_bind_group(
+      "post_lut_bind_group",
+      &lut_bind_group_layout,
+      &lut_texture.create_view(&TextureViewDescriptor::default()),
+      device,
+    );
+
+    Self {
+      output_srgb: output_format.is_srgb(),
+
+      buffer,
+      sampler,
+
+      input_bind_group_layout,
+      texture_bind_group_layout,
+      lut_bind_group_layout,
+
+      targets,
+      bloom_targets,
+      bloom_bind_group,
+
+      lut_texture,
+      lut_bind_group,
+
+      tone_mapping_pipeline,
+      gamma_pipeline,
+      vignette_pipeline,
+      color_grading_pipeline,
+      bloom_prefilter_pipeline,
+      bloom_blur_horizontal_pipeline,
+      bloom_blur_vertical_pipeline,
+      bloom_composite_pipeline,
+      blit_pipeline,
+    }
+  }
+
+  const INPUT_BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 3] = [
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension: TextureViewDimension::D2,
+        sample_type: TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 1,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Sampler(SamplerBindingType::Filtering),
+      count: None,
+    },
+    BindGroupLayoutEntry {
+      binding: 2,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Buffer {
+        ty: BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    },
+  ];
+
+  // Extra texture some effects read next to their input
+  fn texture_bind_layout_entry(
+    view_dimension: TextureViewDimension,
+  ) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::FRAGMENT,
+      ty: BindingType::Texture {
+        multisampled: false,
+        view_dimension,
+        sample_type: TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    }
+  }
+
+  fn create_pipeline(
+    label: &str,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    fragment_entry: &str,
+    format: TextureFormat,
+    device: &Device,
+  ) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some(label),
+      layout: Some(layout),
+      vertex: VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(FragmentState {
+        module: shader,
+        entry_point: fragment_entry,
+        targets: &[Some(ColorTargetState {
+          format,
+          blend: None,
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: None,
+      multisample: MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+
+  fn create_targets(
+    label: &str,
+    width: u32,
+    height: u32,
+    layout: &BindGroupLayout,
+    sampler: &Sampler,
+    buffer: &Buffer,
+    device: &Device,
+  ) -> [PostTarget; 2] {
+    [0, 1].map(|_| {
+      let texture = device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+          width,
+          height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT
+          | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      });
+      let view = texture.create_view(&TextureViewDescriptor::default());
+      let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("post_input_bind_group"),
+        layout,
+        entries: &[
+          BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(&view),
+          },
+          BindGroupEntry {
+            binding: 1,
+            resource: BindingResource::Sampler(sampler),
+          },
+          BindGroupEntry {
+            binding: 2,
+            resource: buffer.as_entire_binding(),
+          },
+        ],
+      });
+      PostTarget {
+        texture,
+        view,
+        bind_group,
+      }
+    })
+  }
+
+  fn create_texture_bind_group(
+    label: &str,
+    layout: &BindGroupLayout,
+    view: &TextureView,
+    device: &Device,
+  ) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+      label: Some(label),
+      layout,
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: BindingResource::TextureView(view),
+      }],
+    })
+  }
+
+  fn create_lut_texture(size: u32, device: &Device) -> Texture {
+    device.create_texture(&TextureDescriptor {
+      label: Some("post_lut_texture"),
+      size: Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: size,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: TextureDimension::D3,
+      format: TextureFormat::Rgba8Unorm,
+      usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+      view_formats: &[],
+    })
+  }
+
+  // RGBA texels with red along x, green along y and blue along z
+  fn write_lut(texture: &Texture, size: u32, texels: &[u8], queue: &Queue) {
+    queue.write_texture(
+      ImageCopyTexture {
+        texture,
+        mip_level: 0,
+        origin: Origin3d::ZERO,
+        aspect: TextureAspect::All,
+      },
+      texels,
+      ImageDataLayout {
+        offset: 0,
+        bytes_per_row: Some(4 * size),
+        rows_per_image: Some(size),
+      },
+      Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: size,
+      },
+    );
+  }
+
+  fn identity_lut(size: u32) -> Vec<u8> {
+    let level = |index: u32| (index * 255 / (size - 1)) as u8;
+    (0..size)
+      .flat_map(|b| {
+        (0..size).flat_map(move |g| {
+          (0..size).flat_map(move |r| [level(r), level(g), level(b), 255])
+        })
+      })
+      .collect()
+  }
+
+  // Replaces the colour grading table with one loaded from an image. The
+  // image is the usual horizontal strip of size * size by size texels, each
+  // square a slice of constant blue
+  pub fn load_lut(
+    &mut self,
+    raw: &[u8],
+    device: &Device,
+    queue: &Queue,
+  ) -> Result<()> {
+    let img = image::load_from_memory(raw)?;
+    let (width, size) = img.dimensions();
+    ensure!(
+      width == size * size,
+      "LUT image is {}x{}, expected {}x{}",
+      width,
+      size,
+      size * size,
+      size
+    );
+
+    let rgba = img.to_rgba8();
+    let texels: Vec<u8> = (0..size)
+      .flat_map(|b| {
+        let rgba = &rgba;
+        (0..size).flat_map(move |g| {
+          (0..size).flat_map(move |r| rgba.get_pixel(b * size + r, g).0)
+        })
+      })
+      .collect();
+
+    let lut_texture = Self::create_lut_texture(size, device);
+    Self::write_lut(&lut_texture, size, &texels, queue);
+    self.lut_bind_group = Self::create_texture_bind_group(
+      "post_lut_bind_group",
+      &self.lut_bind_group_layout,
+      &lut_texture.create_view(&TextureViewDescriptor::default()),
+      device,
+    );
+    self.lut_texture = lut_texture;
+    Ok(())
+  }
+
+  // Where the scene gets rendered before post-processing
+  pub fn scene_view(&self) -> &TextureView {
+    &self.targets[0].view
+  }
+
+  pub fn update(&self, settings: &PostSettings, queue: &Queue) {
+    let uniform = PostUniform {
+      exposure: settings.exposure,
+      gamma: settings.gamma,
+      output_srgb: self.output_srgb as u32,
+      bloom_threshold: settings.bloom_threshold,
+      bloom_intensity: settings.bloom_intensity,
+      vignette_strength: settings.vignette_strength,
+      vignette_radius: settings.vignette_radius,
+      color_grading_strength: settings.color_grading_strength,
+    };
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn draw(
+    encoder: &mut CommandEncoder,
+    label: &str,
+    view: &TextureView,
+    pipeline: &RenderPipeline,
+    bind_groups: &[&BindGroup],
+  ) {
+    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+      label: Some(label),
+      color_attachments: &[Some(RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: Operations {
+          load: LoadOp::Clear(Color::BLACK),
+          store: true,
+        },
+      })],
+      depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(pipeline);
+    bind_groups
+      .iter()
+      .enumerate()
+      .for_each(|(index, bind_group)| {
+        pass.set_bind_group(index as u32, bind_group, &[]);
+      });
+    pass.draw(0..3, 0..1);
+  }
+
+  // Applies the effects to the scene in order, then writes it to the output
+  pub fn render(
+    &self,
+    effects: &[PostEffect],
+    encoder: &mut CommandEncoder,
+    output: &TextureView,
+  ) {
+    let mut current = 0;
+    effects.iter().for_each(|effect| {
+      let input = &self.targets[current].bind_group;
+      let view = &self.targets[1 - current].view;
+      match effect {
+        PostEffect::ToneMapping => Self::draw(
+          encoder,
+          "post_pass:tone_mapping",
+          view,
+          &self.tone_mapping_pipeline,
+          &[input],
+        ),
+        PostEffect::Gamma => Self::draw(
+          encoder,
+          "post_pass:gamma",
+          view,
+          &self.gamma_pipeline,
+          &[input],
+        ),
+        PostEffect::Vignette => Self::draw(
+          encoder,
+          "post_pass:vignette",
+          view,
+          &self.vignette_pipeline,
+          &[input],
+        ),
+        PostEffect::ColorGrading => Self::draw(
+          encoder,
+          "post_pass:color_grading",
+          view,
+          &self.color_grading_pipeline,
+          &[input, &self.lut_bind_group],
+        ),
+        PostEffect::Bloom => {
+          let [first, second] = &self.bloom_targets;
+          Self::draw(
+            encoder,
+            "post_pass:bloom_prefilter",
+            &first.view,
+            &self.bloom_prefilter_pipeline,
+            &[input],
+          );
+          Self::draw(
+            encoder,
+            "post_pass:bloom_blur_horizontal",
+            &second.view,
+            &self.bloom_blur_horizontal_pipeline,
+            &[&first.bind_group],
+          );
+          Self::draw(
+            encoder,
+            "post_pass:bloom_blur_vertical",
+            &first.view,
+            &self.bloom_blur_vertical_pipeline,
+            &[&second.bind_group],
+          );
+          Self::draw(
+            encoder,
+            "post_pass:bloom_composite",
+            view,
+            &self.bloom_composite_pipeline,
+            &[input, &self.bloom_bind_group],
+          );
+        }
+      }
+      current = 1 - current;
+    });
+
+    Self::draw(
+      encoder,
+      "post_pass:blit",
+      output,
+      &self.blit_pipeline,
+      &[&self.targets[current].bind_group],
+    );
+  }
+
+  // The targets must always match the size of the surface
+  pub fn resize(&mut self, width: u32, height: u32, device: &Device) {
+    self.targets = Self::create_targets(
+      "post_texture",
+      width,
+      height,
+      &self.input_bind_group_layout,
+      &self.sampler,
+      &self.buffer,
+      device,
+    );
+    self.bloom_targets = Self::create_targets(
+      "post_bloom_texture",
+      (width / 2).max(1),
+      (height / 2).max(1),
+      &self.input_bind_group_layout,
+      &self.sampler,
+      &self.buffer,
+      device,
+    );
+    self.bloom_bind_group = Self::create_texture_bind_group(
+      "post_bloom_bind_group",
+      &self.texture_bind_group_layout,
+      &self.bloom_targets[0].view,
+      device,
+    );
+  }
+}
@@ -0,0 +1,153 @@
+use cgmath::{Vector3, VectorSpace};
+use wgpu::{
+  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+  BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+  BufferBindingType, BufferDescriptor, BufferUsages, Color, Device, Queue,
+  ShaderStages,
+};
+
+use crate::engine::camera::Camera;
+
+use super::Fog;
+
+// (hour, zenith colour, horizon colour), colours are linear RGB
+#[rustfmt::skip]
+const SKY_KEYFRAMES: [(f32, [f32; 3], [f32; 3]); 8] = [
+  ( 0.0, [0.01, 0.01, 0.03], [0.02, 0.03, 0.06]),
+  ( 5.0, [0.01, 0.01, 0.03], [0.02, 0.03, 0.06]),
+  ( 6.5, [0.15, 0.22, 0.45], [0.85, 0.45, 0.25]),
+  ( 8.0, [0.18, 0.38, 0.80], [0.60, 0.75, 0.92]),
+  (16.0, [0.18, 0.38, 0.80], [0.60, 0.75, 0.92]),
+  (17.5, [0.15, 0.20, 0.45], [0.90, 0.40, 0.20]),
+  (19.0, [0.01, 0.01, 0.03], [0.02, 0.03, 0.06]),
+  (24.0, [0.01, 0.01, 0.03], [0.02, 0.03, 0.06]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+  inverse_view_proj: [[f32; 4]; 4],
+  camera_position: [f32; 4],
+  zenith_color: [f32; 4],
+  horizon_color: [f32; 4],
+  sun_direction: [f32; 4],
+  fog_mode: u32, // 0: disabled, 1: linear, 2: exponential
+  fog_start: f32,
+  fog_end: f32,
+  fog_density: f32,
+}
+
+// Gradient sky, also used as the colour distant geometry fades into
+pub struct Sky {
+  time_of_day: f32, // In hours, 0.0 is midnight and 12.0 is noon
+
+  buffer: Buffer,
+  pub bind_group: BindGroup,
+}
+
+impl Sky {
+  pub fn new(sky_bind_group_layout: &BindGroupLayout, device: &Device) -> Self {
+    let buffer = device.create_buffer(&BufferDescriptor {
+      label: Some("sky_buffer"),
+      size: std::mem::size_of::<SkyUniform>() as u64,
+      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("sky_bind_group"),
+      layout: sky_bind_group_layout,
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: buffer.as_entire_binding(),
+      }],
+    });
+
+    Self {
+      time_of_day: 12.0,
+
+      buffer,
+      bind_group,
+    }
+  }
+
+  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 1] =
+    [BindGroupLayoutEntry {
+      binding: 0,
+      visibility: ShaderStages::VERTEX_FRAGMENT,
+      ty: BindingType::Buffer {
+        ty: BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    }];
+  pub fn bind_group_layout_desc(
+    label: Option<&str>,
+  ) -> BindGroupLayoutDescriptor<'_> {
+    BindGroupLayoutDescriptor {
+      label,
+      entries: &Self::BIND_LAYOUT_ENTRIES,
+    }
+  }
+
+  pub fn time_of_day(&self) -> f32 {
+    self.time_of_day
+  }
+  pub fn set_time_of_day(&mut self, hours: f32) {
+    self.time_of_day = hours.rem_euclid(24.0);
+  }
+
+  // Zenith and horizon colours, interpolated between the keyframes around
+  // the current time of day
+  pub fn colors(&self) -> (Vector3<f32>, Vector3<f32>) {
+    let next = SKY_KEYFRAMES
+      .iter()
+      .position(|(hour, _, _)| *hour > self.time_of_day)
+      .unwrap_or(SKY_KEYFRAMES.len() - 1);
+    let (start_hour, start_zenith, start_horizon) = SKY_KEYFRAMES[next - 1];
+    let (end_hour, end_zenith, end_horizon) = SKY_KEYFRAMES[next];
+    let t = (self.time_of_day - start_hour) / (end_hour - start_hour);
+    (
+      Vector3::from(start_zenith).lerp(end_zenith.into(), t),
+      Vector3::from(start_horizon).lerp(end_horizon.into(), t),
+    )
+  }
+
+  pub fn clear_color(&self) -> Color {
+    let (_, horizon) = self.colors();
+    Color {
+      r: horizon.x as f64,
+      g: horizon.y as f64,
+      b: horizon.z as f64,
+      a: 1.0,
+    }
+  }
+
+  pub fn update(
+    &self,
+    camera: &Camera,
+    sun_direction: Vector3<f32>,
+    fog: Fog,
+    queue: &Queue,
+  ) {
+    let (zenith, horizon) = self.colors();
+    let position = camera.position();
+    let (fog_mode, fog_start, fog_end, fog_density) = match fog {
+      Fog::Disabled => (0, 0.0, 0.0, 0.0),
+      Fog::Linear { start, end } => (1, start, end, 0.0),
+      Fog::Exponential { density } => (2, 0.0, 0.0, density),
+    };
+    let uniform = SkyUniform {
+      inverse_view_proj: camera.inverse_view_proj_matrix().into(),
+      camera_position: [position.x, position.y, position.z, 1.0],
+      zenith_color: zenith.extend(1.0).into(),
+      horizon_color: horizon.extend(1.0).into(),
+      sun_direction: sun_direction.extend(0.0).into(),
+      fog_mode,
+      fog_start,
+      fog_end,
+      fog_density,
+    };
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+  }
+}
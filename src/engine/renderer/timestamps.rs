@@ -0,0 +1,154 @@
+use std::sync::{
+  atomic::{AtomicU8, Ordering},
+  Arc,
+};
+
+use wgpu::{
+  Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Maintain,
+  MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue, QUERY_SIZE,
+};
+
+// Measures how long the GPU spends on each render graph pass through
+// timestamp queries. Results are read back without stalling, so they arrive
+// a frame or more late and frames are skipped while a readback is pending
+pub struct GpuTimer {
+  query_set: QuerySet,
+  resolve_buffer: Buffer,
+  readback_buffer: Buffer,
+  period: f32, // Nanoseconds per timestamp tick
+
+  // Passes measured by the readback in flight, if any
+  pending: Option<Vec<&'static str>>,
+  readback: Arc<AtomicU8>, // One of the READBACK_ states
+  pass_times: Vec<(&'static str, f32)>, // Seconds
+}
+
+impl GpuTimer {
+  // Each pass writes a timestamp before and after it runs
+  pub const MAX_PASSES: u32 = 32;
+
+  // Set by the map callback, the readback is waiting until then
+  const READBACK_WAITING: u8 = 0;
+  const READBACK_MAPPED: u8 = 1;
+  const READBACK_FAILED: u8 = 2;
+
+  pub fn new(device: &Device, queue: &Queue) -> Self {
+    let query_count = Self::MAX_PASSES * 2;
+    let query_set = device.create_query_set(&QuerySetDescriptor {
+      label: Some("gpu_timer_query_set"),
+      ty: QueryType::Timestamp,
+      count: query_count,
+    });
+    let size = (query_count * QUERY_SIZE) as u64;
+    let resolve_buffer = device.create_buffer(&BufferDescriptor {
+      label: Some("gpu_timer_resolve_buffer"),
+      size,
+      usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    });
+    let readback_buffer = device.create_buffer(&BufferDescriptor {
+      label: Some("gpu_timer_readback_buffer"),
+      size,
+      usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    Self {
+      query_set,
+      resolve_buffer,
+      readback_buffer,
+      period: queue.get_timestamp_period(),
+
+      pending: None,
+      readback: Arc::new(AtomicU8::new(Self::READBACK_WAITING)),
+      pass_times: vec![],
+    }
+  }
+
+  // Collects the results of the readback in flight once they are available,
+  // and tells whether the coming frame can be measured
+  pub fn begin_frame(&mut self, device: &Device) -> bool {
+    let Some(passes) = &self.pending else {
+      return true;
+    };
+    device.poll(Maintain::Poll);
+    match self
+      .readback
+      .swap(Self::READBACK_WAITING, Ordering::Acquire)
+    {
+      Self::READBACK_MAPPED => {}
+      // The timestamps of that frame are lost, the next one is measured
+      Self::READBACK_FAILED => {
+        eprintln!("Could not read the GPU timestamps back");
+        self.pending = None;
+        return true;
+      }
+      _ => return false,
+    }
+    {
+      let data = self.readback_buffer.slice(..).get_mapped_range();
+      let timestamps: &[u64] = bytemuck::cast_slice(&data);
+      self.pass_times = passes
+        .iter()
+        .enumerate()
+        .map(|(index, pass)| {
+          let ticks =
+            timestamps[index * 2 + 1].saturating_sub(timestamps[index * 2]);
+          (*pass, ticks as f32 * self.period / 1e9)
+        })
+        .collect();
+    }
+    self.readback_buffer.unmap();
+    self.pending = None;
+    true
+  }
+
+  pub fn query_set(&self) -> &QuerySet {
+    &self.query_set
+  }
+
+  // Copies the timestamps written for the given passes, in the order they
+  // ran, to the readback buffer
+  pub fn resolve(
+    &mut self,
+    passes: Vec<&'static str>,
+    encoder: &mut CommandEncoder,
+  ) {
+    let query_count = passes.len() as u32 * 2;
+    encoder.resolve_query_set(
+      &self.query_set,
+      0..query_count,
+      &self.resolve_buffer,
+      0,
+    );
+    encoder.copy_buffer_to_buffer(
+      &self.resolve_buffer,
+      0,
+      &self.readback_buffer,
+      0,
+      (query_count * QUERY_SIZE) as u64,
+    );
+    self.pending = Some(passes);
+  }
+
+  // Starts reading the resolved timestamps back, once the frame is submitted
+  pub fn map(&self) {
+    let readback = Arc::clone(&self.readback);
+    self
+      .readback_buffer
+      .slice(..)
+      .map_async(MapMode::Read, move |result| {
+        let state = if result.is_ok() {
+          Self::READBACK_MAPPED
+        } else {
+          Self::READBACK_FAILED
+        };
+        readback.store(state, Ordering::Release);
+      });
+  }
+
+  // GPU time of each pass of the last measured frame, in seconds
+  pub fn pass_times(&self) -> &[(&'static str, f32)] {
+    &self.pass_times
+  }
+}
@@ -0,0 +1,31 @@
+struct VertexInput {
+  @location(0) position: vec3<f32>,
+  @location(1) color: vec3<f32>,
+}
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec3<f32>,
+};
+
+struct Camera {
+  projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(
+    line: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = camera.projection * vec4<f32>(line.position, 1.0);
+    out.color = line.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
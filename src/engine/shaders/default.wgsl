@@ -1,12 +1,16 @@
-
 struct VertexInput {
   @location(0) position: vec3<f32>,
   @location(1) tex_coords: vec2<f32>,
+  @location(2) ao: f32,
+  @location(3) normal: vec3<f32>,
 }
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
   @location(0) tex_coords: vec2<f32>,
+  @location(1) ao: f32,
+  @location(2) normal: vec3<f32>,
+  @location(3) world_position: vec3<f32>,
 };
 
 struct Camera {
@@ -23,6 +27,9 @@ fn vs_main(
     var out: VertexOutput;
     out.position = camera.projection * vec4<f32>(model.position, 1.0);
     out.tex_coords = model.tex_coords;
+    out.ao = model.ao;
+    out.normal = model.normal;
+    out.world_position = model.position;
     return out;
 }
 
@@ -32,7 +39,252 @@ var texture_view: texture_2d<f32>;
 @group(1) @binding(1)
 var texture_sampler: sampler;
 
+struct Material {
+  alpha_cutoff: f32,
+  emission: f32,
+}
+
+@group(1) @binding(2)
+var<uniform> material: Material;
+
+const CASCADE_COUNT: u32 = 3u;
+
+struct Lighting {
+  cascade_matrices: array<mat4x4<f32>, CASCADE_COUNT>,
+  cascade_splits: vec4<f32>,
+  camera_position: vec4<f32>,
+  view_direction: vec4<f32>,
+  sun_direction: vec4<f32>,
+  sun_intensity: f32,
+  ambient: f32,
+  shadows_enabled: u32,
+}
+
+@group(2) @binding(0)
+var<uniform> lighting: Lighting;
+
+@group(2) @binding(1)
+var shadow_map: texture_depth_2d_array;
+
+@group(2) @binding(2)
+var shadow_sampler: sampler_comparison;
+
+// Fraction of the sun light reaching a point, filtered over a 3x3 texel area
+fn sun_visibility(world_position: vec3<f32>) -> f32 {
+    if lighting.shadows_enabled == 0u {
+        return 1.0;
+    }
+
+    let view_depth = dot(
+        world_position - lighting.camera_position.xyz,
+        lighting.view_direction.xyz,
+    );
+    var cascade = 0u;
+    for (var i = 0u; i < CASCADE_COUNT - 1u; i++) {
+        if view_depth > lighting.cascade_splits[i] {
+            cascade = i + 1u;
+        }
+    }
+
+    let light_position = lighting.cascade_matrices[cascade]
+        * vec4<f32>(world_position, 1.0);
+    let coords = light_position.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    let depth = light_position.z - 0.002;
+    if any(coords < vec2<f32>(0.0)) || any(coords > vec2<f32>(1.0))
+        || depth > 1.0 {
+        return 1.0;
+    }
+
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var visibility = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            visibility += textureSampleCompareLevel(
+                shadow_map,
+                shadow_sampler,
+                coords + vec2<f32>(f32(x), f32(y)) * texel_size,
+                i32(cascade),
+                depth,
+            );
+        }
+    }
+    return visibility / 9.0;
+}
+
+struct Sky {
+  inverse_view_proj: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  zenith_color: vec4<f32>,
+  horizon_color: vec4<f32>,
+  sun_direction: vec4<f32>,
+  fog_mode: u32,
+  fog_start: f32,
+  fog_end: f32,
+  fog_density: f32,
+}
+
+@group(3) @binding(0)
+var<uniform> sky: Sky;
+
+const FOG_LINEAR: u32 = 1u;
+const FOG_EXPONENTIAL: u32 = 2u;
+
+// Fades distant fragments into the sky behind them
+fn apply_fog(color: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    let offset = world_position - sky.camera_position.xyz;
+    let distance = length(offset);
+    var fog = 0.0;
+    if sky.fog_mode == FOG_LINEAR {
+        fog = clamp(
+            (distance - sky.fog_start) / max(sky.fog_end - sky.fog_start, 1e-4),
+            0.0,
+            1.0,
+        );
+    } else if sky.fog_mode == FOG_EXPONENTIAL {
+        fog = 1.0 - exp(-sky.fog_density * distance);
+    }
+
+    let height = sqrt(clamp(offset.y / distance, 0.0, 1.0));
+    let sky_color = mix(sky.horizon_color.rgb, sky.zenith_color.rgb, height);
+    return mix(color, sky_color, fog);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let color = textureSample(texture_view, texture_sampler, in.tex_coords);
+
+    var diffuse = 1.0;
+    if any(in.normal != vec3<f32>(0.0)) {
+        diffuse = max(dot(normalize(in.normal), lighting.sun_direction.xyz), 0.0);
+    }
+    let sun = diffuse * lighting.sun_intensity
+        * sun_visibility(in.world_position);
+    let light = mix(0.4, 1.0, in.ao)
+        * (lighting.ambient + (1.0 - lighting.ambient) * sun);
+    return vec4<f32>(apply_fog(color.rgb * light, in.world_position), color.a);
+}
+
+// Emissive materials ignore the lighting, but still fade into the fog
+fn emit(in: VertexOutput) -> vec4<f32> {
+    let color = textureSample(texture_view, texture_sampler, in.tex_coords);
+    let emitted = color.rgb * material.emission;
+    return vec4<f32>(apply_fog(emitted, in.world_position), color.a);
+}
+
+// Cutout texels are either fully opaque or discarded
+fn cutout(color: vec4<f32>) -> vec4<f32> {
+    if color.a < material.alpha_cutoff {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+// With alpha to coverage, the alpha sharpened around the cutoff becomes the
+// fraction of samples covered, which antialiases the cutout edges
+fn cutout_coverage(color: vec4<f32>) -> vec4<f32> {
+    let alpha = (color.a - material.alpha_cutoff)
+        / max(fwidth(color.a), 1e-4) + 0.5;
+    return vec4<f32>(color.rgb, clamp(alpha, 0.0, 1.0));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture_view, texture_sampler, in.tex_coords);
+    return shade(in);
+}
+
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    return cutout(shade(in));
+}
+
+@fragment
+fn fs_cutout_coverage(in: VertexOutput) -> @location(0) vec4<f32> {
+    return cutout_coverage(shade(in));
+}
+
+@fragment
+fn fs_emissive(in: VertexOutput) -> @location(0) vec4<f32> {
+    return emit(in);
+}
+
+@fragment
+fn fs_emissive_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    return cutout(emit(in));
+}
+
+@fragment
+fn fs_emissive_cutout_coverage(in: VertexOutput) -> @location(0) vec4<f32> {
+    return cutout_coverage(emit(in));
+}
+
+struct OitOutput {
+  @location(0) accumulation: vec4<f32>,
+  @location(1) revealage: f32,
+}
+
+// Weighted blended order independent transparency (McGuire & Bavoil 2013),
+// fragments closer to the camera get a higher weight. Weighted by distance
+// rather than depth, which depends on the camera depth range
+@fragment
+fn fs_oit(in: VertexOutput) -> OitOutput {
+    let color = shade(in);
+    let distance = length(in.world_position - lighting.camera_position.xyz);
+    let weight = color.a
+        * clamp(0.03 / (1e-5 + pow(distance / 200.0, 4.0)), 1e-2, 3e3);
+
+    var out: OitOutput;
+    out.accumulation = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
+
+// Debug views, selected through the renderer settings
+
+@fragment
+fn fs_debug_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(normalize(in.normal) * 0.5 + 0.5, 1.0);
+}
+
+// Stable, well spread colour for each chunk
+fn chunk_color(chunk: vec3<i32>) -> vec3<f32> {
+    var hash = (bitcast<u32>(chunk.x) * 73856093u)
+        ^ (bitcast<u32>(chunk.y) * 19349663u)
+        ^ (bitcast<u32>(chunk.z) * 83492791u);
+    hash = (hash ^ (hash >> 13u)) * 1274126177u;
+    let channels = vec3<u32>(hash, hash >> 8u, hash >> 16u) & vec3<u32>(255u);
+    return vec3<f32>(channels) / 255.0 * 0.7 + 0.3;
+}
+
+@fragment
+fn fs_debug_chunks(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Faces lie on block boundaries, step inside the block they belong to
+    let block = floor(in.world_position - in.normal * 0.5);
+    let chunk = vec3<i32>(floor(block / 32.0));
+    // Faces facing different ways stay distinguishable
+    let facing = 0.7 + 0.3 * abs(dot(in.normal, vec3<f32>(0.27, 0.8, 0.53)));
+    return vec4<f32>(chunk_color(chunk) * facing, 1.0);
+}
+
+// Drawn additively, so brighter means more fragments for the pixel
+@fragment
+fn fs_debug_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.08, 0.04, 0.02, 1.0);
+}
+
+@fragment
+fn fs_debug_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.9, 0.9, 0.9, 1.0);
+}
+
+// Without line polygon mode, faces are filled and only kept close to the
+// edges of the unit cells they cover. Shows block faces rather than triangles
+@fragment
+fn fs_debug_wireframe_fallback(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = fract(in.world_position);
+    // The axis along the normal is always on a cell edge, push it away
+    let edge = min(cell, 1.0 - cell) + abs(in.normal);
+    let distance = min(edge.x, min(edge.y, edge.z));
+    if distance > fwidth(distance) * 1.5 {
+        discard;
+    }
+    return vec4<f32>(0.9, 0.9, 0.9, 1.0);
 }
@@ -0,0 +1,73 @@
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+const FXAA_REDUCE_MIN: f32 = 0.0078125; // 1 / 128
+const FXAA_REDUCE_MUL: f32 = 0.125;
+const FXAA_SPAN_MAX: f32 = 8.0;
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Perceptual brightness, edges are searched for on it rather than on colour
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn sample_scene(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(scene_texture, scene_sampler, uv, 0.0).rgb;
+}
+
+// Blurs along the edge direction estimated from the luma of the four
+// diagonal neighbours, falling back to a narrower blur when the wider one
+// picks up colours from outside the local contrast range
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(scene_texture));
+    let uv = position.xy * texel;
+
+    let luma_nw = luma(sample_scene(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_scene(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_scene(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_scene(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(sample_scene(uv));
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN,
+    );
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(
+        direction * scale,
+        vec2<f32>(-FXAA_SPAN_MAX),
+        vec2<f32>(FXAA_SPAN_MAX),
+    ) * texel;
+
+    let narrow = 0.5 * (
+        sample_scene(uv + direction * (1.0 / 3.0 - 0.5)) +
+        sample_scene(uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    let wide = narrow * 0.5 + 0.25 * (
+        sample_scene(uv - direction * 0.5) +
+        sample_scene(uv + direction * 0.5)
+    );
+
+    let luma_wide = luma(wide);
+    if luma_wide < luma_min || luma_wide > luma_max {
+        return vec4<f32>(narrow, 1.0);
+    }
+    return vec4<f32>(wide, 1.0);
+}
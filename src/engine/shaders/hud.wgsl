@@ -0,0 +1,35 @@
+struct VertexInput {
+  @location(0) position: vec2<f32>,
+  @location(1) tex_coords: vec2<f32>,
+  @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) tex_coords: vec2<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var s_atlas: sampler;
+
+// Positions are already in clip space
+@vertex
+fn vs_main(
+    quad: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(quad.position, 0.0, 1.0);
+    out.tex_coords = quad.tex_coords;
+    out.color = quad.color;
+    return out;
+}
+
+// The atlas only stores coverage, the colour comes from the vertices
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(t_atlas, s_atlas, in.tex_coords).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
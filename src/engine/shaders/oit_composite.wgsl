@@ -0,0 +1,24 @@
+@group(0) @binding(0)
+var accumulation_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var revealage_texture: texture_2d<f32>;
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(position.xy);
+    let revealage = textureLoad(revealage_texture, coords, 0).r;
+    if revealage >= 1.0 {
+        discard;
+    }
+    let accumulation = textureLoad(accumulation_texture, coords, 0);
+    let color = accumulation.rgb / max(accumulation.a, 1e-5);
+    return vec4<f32>(color, 1.0 - revealage);
+}
@@ -0,0 +1,39 @@
+struct PostUniform {
+    exposure: f32,
+    gamma: f32,
+    output_srgb: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    color_grading_strength: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> post: PostUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(input_texture, input_sampler, in.uv);
+}
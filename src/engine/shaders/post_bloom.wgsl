@@ -0,0 +1,82 @@
+struct PostUniform {
+    exposure: f32,
+    gamma: f32,
+    output_srgb: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    color_grading_strength: f32,
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> post: PostUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@group(1) @binding(0)
+var bloom_texture: texture_2d<f32>;
+
+const BLUR_WEIGHTS: array<f32, 5> = array<f32, 5>(
+    0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216
+);
+
+// Keeps what is brighter than the threshold, while downsampling to the half
+// resolution bloom is blurred at
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - post.bloom_threshold, 0.0)
+        / max(brightness, 1e-4);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    // Constant arrays can only be indexed by constants
+    var weights = BLUR_WEIGHTS;
+    let step = direction / vec2<f32>(textureDimensions(input_texture));
+    var color = textureSample(input_texture, input_sampler, uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = step * f32(i);
+        color += textureSample(input_texture, input_sampler, uv + offset).rgb * weights[i];
+        color += textureSample(input_texture, input_sampler, uv - offset).rgb * weights[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, in.uv);
+    let bloom = textureSample(bloom_texture, input_sampler, in.uv).rgb;
+    return vec4<f32>(color.rgb + bloom * post.bloom_intensity, color.a);
+}
@@ -0,0 +1,17 @@
+struct VertexInput {
+  @location(0) position: vec3<f32>,
+}
+
+struct Cascade {
+  projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> cascade: Cascade;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> @builtin(position) vec4<f32> {
+    return cascade.projection * vec4<f32>(model.position, 1.0);
+}
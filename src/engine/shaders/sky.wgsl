@@ -0,0 +1,46 @@
+struct Sky {
+  inverse_view_proj: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  zenith_color: vec4<f32>,
+  horizon_color: vec4<f32>,
+  sun_direction: vec4<f32>,
+  fog_mode: u32,
+  fog_start: f32,
+  fog_end: f32,
+  fog_density: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) ndc: vec2<f32>,
+};
+
+// Single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.position = vec4<f32>(out.ndc, 1.0, 1.0);
+    return out;
+}
+
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let height = sqrt(clamp(direction.y, 0.0, 1.0));
+    return mix(sky.horizon_color.rgb, sky.zenith_color.rgb, height);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let far = sky.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - sky.camera_position.xyz);
+
+    var color = sky_color(direction);
+    let sun = smoothstep(0.9990, 0.9995, dot(direction, sky.sun_direction.xyz))
+        * step(0.0, direction.y);
+    color = mix(color, vec3<f32>(1.0, 0.95, 0.8), sun);
+    return vec4<f32>(color, 1.0);
+}
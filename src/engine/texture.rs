@@ -1,13 +1,10 @@
 use anyhow::*;
 use image::{DynamicImage, GenericImageView};
 use wgpu::{
-  AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-  BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Device,
-  Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
-  Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, Texture,
-  TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
-  TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
-  TextureViewDimension,
+  AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout,
+  Origin3d, Queue, Sampler, SamplerDescriptor, Texture, TextureAspect,
+  TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+  TextureView, TextureViewDescriptor,
 };
 
 #[derive(Debug)]
@@ -15,24 +12,21 @@ pub struct BloomTexture {
   pub texture: Texture,
   pub view: TextureView,
   pub sampler: Sampler,
-  pub bind_group: BindGroup,
 }
 
 impl BloomTexture {
   pub fn from_raw_rbga(
     label: &str,
     raw: &[u8],
-    bind_layout: &BindGroupLayout,
     device: &Device,
     queue: &Queue,
   ) -> Result<Self> {
     let img = image::load_from_memory(raw)?;
-    Self::from_img(label, &img, bind_layout, device, queue)
+    Self::from_img(label, &img, device, queue)
   }
   pub fn from_img(
     label: &str,
     img: &DynamicImage,
-    bind_layout: &BindGroupLayout,
     device: &Device,
     queue: &Queue,
   ) -> Result<Self> {
@@ -83,51 +77,10 @@ impl BloomTexture {
       ..Default::default()
     });
 
-    let bind_group = device.create_bind_group(&BindGroupDescriptor {
-      label: Some(label),
-      layout: bind_layout,
-      entries: &[
-        BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(&view),
-        },
-        BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::Sampler(&sampler),
-        },
-      ],
-    });
-
     Ok(Self {
       texture,
       view,
       sampler,
-      bind_group,
     })
   }
-
-  const BIND_LAYOUT_ENTRIES: [BindGroupLayoutEntry; 2] = [
-    BindGroupLayoutEntry {
-      binding: 0,
-      visibility: ShaderStages::FRAGMENT,
-      ty: wgpu::BindingType::Texture {
-        multisampled: false,
-        view_dimension: TextureViewDimension::D2,
-        sample_type: TextureSampleType::Float { filterable: true },
-      },
-      count: None,
-    },
-    BindGroupLayoutEntry {
-      binding: 1,
-      visibility: ShaderStages::FRAGMENT,
-      ty: BindingType::Sampler(SamplerBindingType::Filtering),
-      count: None,
-    },
-  ];
-  pub fn bind_group_layout() -> BindGroupLayoutDescriptor<'static> {
-    BindGroupLayoutDescriptor {
-      label: Some("texture_bind_group_layout"),
-      entries: &Self::BIND_LAYOUT_ENTRIES,
-    }
-  }
 }
@@ -1,35 +1,175 @@
+pub mod action;
+pub mod replay;
+
 use std::collections::HashSet;
 use winit::{
-  event::{Event, WindowEvent},
+  event::{DeviceEvent, Event, MouseScrollDelta, WindowEvent},
   keyboard::{KeyCode, PhysicalKey},
 };
 
+use self::action::{Action, ActionMap, Button};
+
+// What Input keeps from the window events, independently of the platform
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+  Button { button: Button, pressed: bool },
+  MouseMotion(f32, f32), // In pixels
+  Scroll(f32),           // In lines
+  FocusLost,
+}
+
+// Input state of the current frame. Events update it as they come in, and
+// `end_frame` clears what only lasts a frame once the frame was updated
 pub struct Input {
-  kbd: HashSet<KeyCode>,
+  events: Vec<InputEvent>, // Since the last frame
+  held: HashSet<Button>,
+  pressed: HashSet<Button>,  // Since the last frame
+  released: HashSet<Button>, // Same
+  mouse_motion: (f32, f32),  // In pixels, same
+  scroll: f32,               // In lines, same
+  pub bindings: ActionMap,
 }
 
 impl Input {
-  pub fn new() -> Self {
+  pub fn new(bindings: ActionMap) -> Self {
     Self {
-      kbd: HashSet::new(),
+      events: vec![],
+      held: HashSet::new(),
+      pressed: HashSet::new(),
+      released: HashSet::new(),
+      mouse_motion: (0.0, 0.0),
+      scroll: 0.0,
+      bindings,
     }
   }
 
   pub fn update(&mut self, event: &Event<()>) {
+    if let Some(event) = Self::input_event(event) {
+      self.apply(event);
+    }
+  }
+
+  fn input_event(event: &Event<()>) -> Option<InputEvent> {
     match event {
-      Event::WindowEvent {
-        event: WindowEvent::KeyboardInput { event, .. },
-        ..
-      } => {
-        if let PhysicalKey::Code(code) = event.physical_key {
-          if event.state.is_pressed() {
-            self.kbd.insert(code);
-          } else {
-            self.kbd.remove(&code);
+      Event::WindowEvent { event, .. } => match event {
+        WindowEvent::KeyboardInput { event, .. } => {
+          match event.physical_key {
+            // Key repeats are not presses
+            PhysicalKey::Code(code) if !event.repeat => {
+              Some(InputEvent::Button {
+                button: Button::Key(code),
+                pressed: event.state.is_pressed(),
+              })
+            }
+            _ => None,
           }
         }
+        WindowEvent::MouseInput { state, button, .. } => {
+          Some(InputEvent::Button {
+            button: Button::Mouse(*button),
+            pressed: state.is_pressed(),
+          })
+        }
+        WindowEvent::MouseWheel { delta, .. } => {
+          Some(InputEvent::Scroll(match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+          }))
+        }
+        WindowEvent::Focused(false) => Some(InputEvent::FocusLost),
+        _ => None,
+      },
+      // Raw motion keeps coming while the cursor is locked in place, unlike
+      // cursor positions
+      Event::DeviceEvent {
+        event: DeviceEvent::MouseMotion { delta },
+        ..
+      } => Some(InputEvent::MouseMotion(delta.0 as f32, delta.1 as f32)),
+      _ => None,
+    }
+  }
+
+  // Also how replays feed recorded events back
+  pub fn apply(&mut self, event: InputEvent) {
+    self.events.push(event);
+    match event {
+      InputEvent::Button { button, pressed } => {
+        self.set_button(button, pressed)
+      }
+      InputEvent::MouseMotion(dx, dy) => {
+        self.mouse_motion.0 += dx;
+        self.mouse_motion.1 += dy;
+      }
+      InputEvent::Scroll(lines) => self.scroll += lines,
+      // Releases happening outside of the window never arrive
+      InputEvent::FocusLost => self.released.extend(self.held.drain()),
+    }
+  }
+
+  fn set_button(&mut self, button: Button, pressed: bool) {
+    if pressed {
+      if self.held.insert(button) {
+        self.pressed.insert(button);
       }
-      _ => {}
+    } else if self.held.remove(&button) {
+      self.released.insert(button);
     }
   }
+
+  pub fn end_frame(&mut self) {
+    self.events.clear();
+    self.pressed.clear();
+    self.released.clear();
+    self.mouse_motion = (0.0, 0.0);
+    self.scroll = 0.0;
+  }
+
+  pub fn events(&self) -> &[InputEvent] {
+    &self.events
+  }
+
+  pub fn button_held(&self, button: Button) -> bool {
+    self.held.contains(&button)
+  }
+  pub fn button_pressed(&self, button: Button) -> bool {
+    self.pressed.contains(&button)
+  }
+  pub fn button_released(&self, button: Button) -> bool {
+    self.released.contains(&button)
+  }
+  pub fn key_held(&self, code: KeyCode) -> bool {
+    self.button_held(Button::Key(code))
+  }
+
+  // Buttons pressed during the frame, to pick a button to rebind an
+  // action to
+  pub fn pressed_buttons(&self) -> impl Iterator<Item = Button> + '_ {
+    self.pressed.iter().copied()
+  }
+
+  pub fn held(&self, action: Action) -> bool {
+    let buttons = self.bindings.buttons(action);
+    buttons.iter().any(|button| self.button_held(*button))
+  }
+  pub fn pressed(&self, action: Action) -> bool {
+    let buttons = self.bindings.buttons(action);
+    buttons.iter().any(|button| self.button_pressed(*button))
+  }
+  pub fn released(&self, action: Action) -> bool {
+    let buttons = self.bindings.buttons(action);
+    buttons.iter().any(|button| self.button_released(*button))
+  }
+
+  // 1 while only the positive action is held, -1 while only the negative
+  // one is, 0 otherwise
+  pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+    self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+  }
+
+  pub fn mouse_motion(&self) -> (f32, f32) {
+    self.mouse_motion
+  }
+  pub fn scroll(&self) -> f32 {
+    self.scroll
+  }
 }
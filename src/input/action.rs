@@ -0,0 +1,265 @@
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+  str::FromStr,
+};
+
+use anyhow::*;
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+// What the player wants to do, independently of the buttons doing it
+#[derive(
+  Debug, EnumIter, EnumString, IntoStaticStr, Clone, Copy, PartialEq, Eq, Hash,
+)]
+pub enum Action {
+  MoveForward,
+  MoveBackward,
+  MoveLeft,
+  MoveRight,
+  MoveUp,
+  MoveDown,
+  Sprint,
+  RollLeft,
+  RollRight,
+  ZoomIn,
+  ZoomOut,
+  GrabCursor,
+  ReleaseCursor,
+  PlaceBlock,
+  BreakBlock,
+  NextBlock,
+  PreviousBlock,
+  Undo,
+  Redo,
+  NextController,
+  NextProjection,
+  NextTransparency,
+  NextAntialiasing,
+  PrintCamera,
+  RecordCameraPath,
+  PlayCameraPath,
+  ToggleDebug,
+  NextDebugView,
+  ToggleChunkBounds,
+  NextDayPeriod,
+  PauseClock,
+}
+
+impl Action {
+  pub fn name(&self) -> &'static str {
+    self.into()
+  }
+
+  fn default_buttons(&self) -> Vec<Button> {
+    use Button::{Key, Mouse};
+    match self {
+      Action::MoveForward => vec![Key(KeyCode::KeyW)],
+      Action::MoveBackward => vec![Key(KeyCode::KeyS)],
+      Action::MoveLeft => vec![Key(KeyCode::KeyA)],
+      Action::MoveRight => vec![Key(KeyCode::KeyD)],
+      Action::MoveUp => vec![Key(KeyCode::Space)],
+      Action::MoveDown => vec![Key(KeyCode::ShiftLeft)],
+      Action::Sprint => vec![Key(KeyCode::ControlLeft)],
+      Action::RollLeft => vec![Key(KeyCode::KeyQ)],
+      Action::RollRight => vec![Key(KeyCode::KeyE)],
+      Action::ZoomIn => vec![Key(KeyCode::KeyP)],
+      Action::ZoomOut => vec![Key(KeyCode::KeyO)],
+      Action::GrabCursor => vec![Mouse(MouseButton::Left)],
+      Action::ReleaseCursor => vec![Key(KeyCode::Escape)],
+      Action::PlaceBlock => vec![Mouse(MouseButton::Right)],
+      Action::BreakBlock => vec![Mouse(MouseButton::Left)],
+      Action::NextBlock => vec![Key(KeyCode::BracketRight)],
+      Action::PreviousBlock => vec![Key(KeyCode::BracketLeft)],
+      Action::Undo => vec![Key(KeyCode::KeyZ)],
+      Action::Redo => vec![Key(KeyCode::KeyY)],
+      Action::NextController => vec![Key(KeyCode::KeyC)],
+      Action::NextProjection => vec![Key(KeyCode::F7)],
+      Action::NextTransparency => vec![Key(KeyCode::F4)],
+      Action::NextAntialiasing => vec![Key(KeyCode::F2)],
+      Action::PrintCamera => vec![Key(KeyCode::F8)],
+      Action::RecordCameraPath => vec![Key(KeyCode::F9)],
+      Action::PlayCameraPath => vec![Key(KeyCode::F10)],
+      Action::ToggleDebug => vec![Key(KeyCode::F3)],
+      Action::NextDebugView => vec![Key(KeyCode::F5)],
+      Action::ToggleChunkBounds => vec![Key(KeyCode::F6)],
+      Action::NextDayPeriod => vec![Key(KeyCode::KeyN)],
+      Action::PauseClock => vec![Key(KeyCode::KeyM)],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+  Key(KeyCode),
+  Mouse(MouseButton),
+}
+
+impl Button {
+  // Keys which can be named in the bindings file
+  #[rustfmt::skip]
+  const KEYS: &'static [KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE,
+    KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ,
+    KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO,
+    KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT,
+    KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7,
+    KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5,
+    KeyCode::F6, KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10,
+    KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Tab, KeyCode::Escape,
+    KeyCode::Backspace, KeyCode::Delete, KeyCode::Insert, KeyCode::Home,
+    KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft,
+    KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft,
+    KeyCode::BracketRight, KeyCode::Semicolon, KeyCode::Quote,
+    KeyCode::Comma, KeyCode::Period,
+  ];
+
+  // Keys are named like winit's KeyCode variants, mouse buttons MouseLeft,
+  // MouseRight, MouseMiddle or Mouse followed by the button number
+  pub fn name(&self) -> String {
+    match self {
+      Button::Key(code) => format!("{:?}", code),
+      Button::Mouse(MouseButton::Left) => String::from("MouseLeft"),
+      Button::Mouse(MouseButton::Right) => String::from("MouseRight"),
+      Button::Mouse(MouseButton::Middle) => String::from("MouseMiddle"),
+      Button::Mouse(MouseButton::Back) => String::from("MouseBack"),
+      Button::Mouse(MouseButton::Forward) => String::from("MouseForward"),
+      Button::Mouse(MouseButton::Other(number)) => format!("Mouse{}", number),
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Button> {
+    let mouse = match name {
+      "MouseLeft" => Some(MouseButton::Left),
+      "MouseRight" => Some(MouseButton::Right),
+      "MouseMiddle" => Some(MouseButton::Middle),
+      "MouseBack" => Some(MouseButton::Back),
+      "MouseForward" => Some(MouseButton::Forward),
+      _ => name
+        .strip_prefix("Mouse")
+        .and_then(|number| number.parse().ok())
+        .map(MouseButton::Other),
+    };
+    mouse.map(Button::Mouse).or_else(|| {
+      Self::KEYS
+        .iter()
+        .copied()
+        .find(|code| format!("{:?}", code) == name)
+        .map(Button::Key)
+    })
+  }
+}
+
+// Buttons bound to each action. The same button can trigger several actions,
+// and an action is triggered by any of its buttons
+pub struct ActionMap {
+  bindings: HashMap<Action, Vec<Button>>,
+}
+
+impl Default for ActionMap {
+  fn default() -> Self {
+    Self {
+      bindings: Action::iter()
+        .map(|action| (action, action.default_buttons()))
+        .collect(),
+    }
+  }
+}
+
+impl ActionMap {
+  // The defaults, overridden by the file the BLOOM_BINDINGS environment
+  // variable names, if any
+  pub fn from_env() -> Result<Self> {
+    let mut bindings = Self::default();
+    if let Some(path) = std::env::var_os("BLOOM_BINDINGS") {
+      bindings.load(Path::new(&path))?;
+    }
+    Ok(bindings)
+  }
+
+  pub fn buttons(&self, action: Action) -> &[Button] {
+    self
+      .bindings
+      .get(&action)
+      .map_or(&[], |buttons| &buttons[..])
+  }
+
+  pub fn bind(&mut self, action: Action, button: Button) {
+    let buttons = self.bindings.entry(action).or_default();
+    if !buttons.contains(&button) {
+      buttons.push(button);
+    }
+  }
+
+  pub fn unbind(&mut self, action: Action, button: Button) {
+    if let Some(buttons) = self.bindings.get_mut(&action) {
+      buttons.retain(|bound| *bound != button);
+    }
+  }
+
+  // Replaces every button of the action
+  pub fn rebind(&mut self, action: Action, buttons: &[Button]) {
+    self.bindings.insert(action, buttons.to_vec());
+  }
+
+  // Text file with an `Action = Button, Button` line per rebound action.
+  // Actions missing from the file keep their current buttons, and an action
+  // without any button after the equal sign is left unbound
+  pub fn load(&mut self, path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    self
+      .parse(&text)
+      .with_context(|| format!("in {}", path.display()))
+  }
+
+  pub fn parse(&mut self, text: &str) -> Result<()> {
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let Some((action, buttons)) = line.split_once('=') else {
+        bail!("line {}: expected `Action = Button, Button`", number + 1);
+      };
+      let action = Action::from_str(action.trim()).map_err(|_| {
+        anyhow!("line {}: unknown action {}", number + 1, action)
+      })?;
+      let buttons = buttons
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+          Button::from_name(name).ok_or_else(|| {
+            anyhow!("line {}: unknown button {}", number + 1, name)
+          })
+        })
+        .collect::<Result<Vec<_>>>()?;
+      self.rebind(action, &buttons);
+    }
+    Ok(())
+  }
+
+  // Writes every binding, in the format `load` reads
+  pub fn save(&self, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for action in Action::iter() {
+      let buttons: Vec<String> =
+        self.buttons(action).iter().map(Button::name).collect();
+      writeln!(file, "{} = {}", action.name(), buttons.join(", "))?;
+    }
+    file.flush()?;
+    Ok(())
+  }
+}
@@ -0,0 +1,335 @@
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+use anyhow::*;
+use cgmath::{MetricSpace, Point3};
+
+use super::{action::Button, InputEvent};
+
+// State at the end of a recorded session, which replays have to reach again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionEnd {
+  pub world_digest: u64,
+  pub camera_position: Point3<f32>,
+}
+
+impl SessionEnd {
+  const CAMERA_TOLERANCE: f32 = 1e-3;
+
+  pub fn check(&self, reached: SessionEnd) -> Result<()> {
+    if self.world_digest != reached.world_digest {
+      bail!(
+        "world digest {:016x} instead of {:016x}",
+        reached.world_digest,
+        self.world_digest
+      );
+    }
+    let distance = self.camera_position.distance(reached.camera_position);
+    if distance > Self::CAMERA_TOLERANCE {
+      bail!(
+        "camera at {:?} instead of {:?}",
+        reached.camera_position,
+        self.camera_position
+      );
+    }
+    Ok(())
+  }
+}
+
+pub struct RecordedFrame {
+  pub delta: f32, // Seconds
+  pub events: Vec<InputEvent>,
+}
+
+// Writes the input events of every frame, with the frame time they were
+// updated with, to the file the BLOOM_INPUT_RECORD environment variable
+// names. One `frame number delta` line per frame, followed by a line per
+// event and an `end` line with the state the session ended in
+pub struct InputRecorder {
+  file: BufWriter<File>,
+  frame: u64,
+}
+
+impl InputRecorder {
+  pub fn new(file: File) -> Self {
+    Self {
+      file: BufWriter::new(file),
+      frame: 0,
+    }
+  }
+
+  pub fn from_env() -> Result<Option<Self>> {
+    match std::env::var_os("BLOOM_INPUT_RECORD") {
+      Some(path) => Ok(Some(Self::new(File::create(path)?))),
+      None => Ok(None),
+    }
+  }
+
+  pub fn record_frame(
+    &mut self,
+    delta: f32,
+    events: &[InputEvent],
+  ) -> Result<()> {
+    writeln!(self.file, "frame {} {}", self.frame, delta)?;
+    for event in events {
+      match event {
+        // Buttons without a name can't be bound to actions either
+        InputEvent::Button { button, .. }
+          if Button::from_name(&button.name()).is_none() => {}
+        InputEvent::Button { button, pressed } => {
+          let state = if *pressed { "press" } else { "release" };
+          writeln!(self.file, "{} {}", state, button.name())?;
+        }
+        InputEvent::MouseMotion(dx, dy) => {
+          writeln!(self.file, "motion {} {}", dx, dy)?
+        }
+        InputEvent::Scroll(lines) => writeln!(self.file, "scroll {}", lines)?,
+        InputEvent::FocusLost => writeln!(self.file, "unfocus")?,
+      }
+    }
+    self.frame += 1;
+    Ok(())
+  }
+
+  pub fn finish(mut self, end: SessionEnd) -> Result<()> {
+    let position = end.camera_position;
+    writeln!(
+      self.file,
+      "end {:016x} {} {} {}",
+      end.world_digest, position.x, position.y, position.z
+    )?;
+    self.file.flush()?;
+    Ok(())
+  }
+}
+
+// Recorded frames, fed back into the engine loop in place of the window
+// events. Replays only reach the recorded end when they start from the same
+// world and camera, so from the same BLOOM_SCENE
+pub struct InputReplay {
+  frames: std::vec::IntoIter<RecordedFrame>,
+  end: Option<SessionEnd>, // Missing when the recording was interrupted
+}
+
+impl InputReplay {
+  pub fn load(path: &Path) -> Result<Self> {
+    let text = std::fs::read_to_string(path)?;
+    Self::parse(&text).with_context(|| format!("in {}", path.display()))
+  }
+
+  // Replays the file the BLOOM_INPUT_REPLAY environment variable names
+  pub fn from_env() -> Result<Option<Self>> {
+    match std::env::var_os("BLOOM_INPUT_REPLAY") {
+      Some(path) => Ok(Some(Self::load(Path::new(&path))?)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn parse(text: &str) -> Result<Self> {
+    let mut frames: Vec<RecordedFrame> = vec![];
+    let mut end = None;
+    for (number, line) in text.lines().enumerate() {
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      let error = || anyhow!("line {}: malformed `{}`", number + 1, line);
+      let float = |index: usize| -> Result<f32> {
+        fields
+          .get(index)
+          .and_then(|field| field.parse().ok())
+          .ok_or_else(error)
+      };
+      let event = match fields[..] {
+        [] => continue,
+        ["frame", frame, _] => {
+          let frame: u64 = frame.parse().map_err(|_| error())?;
+          // Frames are numbered from 0, so skipped or reordered frames
+          // would replay different input
+          if frame != frames.len() as u64 {
+            bail!(
+              "line {}: frame {} where frame {} was expected",
+              number + 1,
+              frame,
+              frames.len()
+            );
+          }
+          frames.push(RecordedFrame {
+            delta: float(2)?,
+            events: vec![],
+          });
+          continue;
+        }
+        ["end", digest, _, _, _] => {
+          end = Some(SessionEnd {
+            world_digest: u64::from_str_radix(digest, 16)
+              .map_err(|_| error())?,
+            camera_position: Point3::new(float(2)?, float(3)?, float(4)?),
+          });
+          continue;
+        }
+        [state @ ("press" | "release"), name] => InputEvent::Button {
+          button: Button::from_name(name).ok_or_else(error)?,
+          pressed: state == "press",
+        },
+        ["motion", _, _] => InputEvent::MouseMotion(float(1)?, float(2)?),
+        ["scroll", _] => InputEvent::Scroll(float(1)?),
+        ["unfocus"] => InputEvent::FocusLost,
+        _ => return Err(error()),
+      };
+      frames.last_mut().ok_or_else(error)?.events.push(event);
+    }
+    Ok(Self {
+      frames: frames.into_iter(),
+      end,
+    })
+  }
+
+  pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+    self.frames.next()
+  }
+
+  pub fn end(&self) -> Option<SessionEnd> {
+    self.end
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use cgmath::Vector3;
+  use winit::{event::MouseButton, keyboard::KeyCode};
+
+  use super::*;
+  use crate::{
+    engine::{
+      camera::Camera,
+      game::{
+        block::{model::BlockModel, registry::BlockRegistry, Block},
+        editor::BlockEditor,
+        hotbar::Hotbar,
+        scene::Scene,
+        state::GameState,
+        world::history::EditHistory,
+      },
+    },
+    input::{action::ActionMap, Input},
+  };
+
+  // The default scene, the camera in front of its stone block
+  fn game() -> (GameState, Camera) {
+    let model = Rc::new(BlockModel::model_simple());
+    let mut registry = BlockRegistry::new();
+    ["stone", "oak_log", "glass"].iter().for_each(|name| {
+      registry.register_block(&Rc::new(Block::without_material(name, &model)))
+    });
+    let mut camera = Camera::new(16.0 / 9.0);
+    camera.set_position(Point3::new(1.5, 1.5, 5.0));
+    camera.look_at(Point3::new(1.5, 1.5, 1.5));
+    let editor =
+      BlockEditor::new(Hotbar::from_registry(&registry), EditHistory::new(10));
+    let state =
+      GameState::new(Scene::Default.build(&registry), editor, None, &camera);
+    (state, camera)
+  }
+
+  fn button(button: Button, pressed: bool) -> InputEvent {
+    InputEvent::Button { button, pressed }
+  }
+
+  fn key(code: KeyCode, pressed: bool) -> InputEvent {
+    button(Button::Key(code), pressed)
+  }
+
+  #[test]
+  fn replays_reach_the_recorded_end() -> Result<()> {
+    let path = std::env::temp_dir()
+      .join(format!("bloom_replay_{}.txt", std::process::id()));
+    let (left, right) = (
+      Button::Mouse(MouseButton::Left),
+      Button::Mouse(MouseButton::Right),
+    );
+    // Grabs the cursor, places a block against the stone one, then walks
+    // and looks around
+    let script = vec![
+      (0.016, vec![button(left, true)]),
+      (0.017, vec![button(left, false)]),
+      (0.0161, vec![button(right, true)]),
+      (0.02, vec![button(right, false), key(KeyCode::KeyW, true)]),
+      (0.015, vec![InputEvent::MouseMotion(3.5, -1.25)]),
+      (
+        0.016,
+        vec![key(KeyCode::KeyW, false), key(KeyCode::KeyD, true)],
+      ),
+      (0.015, vec![InputEvent::Scroll(1.0), InputEvent::FocusLost]),
+      (0.1, vec![]),
+    ];
+
+    let mut recorder = InputRecorder::new(File::create(&path)?);
+    let mut input = Input::new(ActionMap::default());
+    let (mut state, mut camera) = game();
+    let start = state.session_end(&camera);
+    for (delta, events) in script {
+      events.into_iter().for_each(|event| input.apply(event));
+      recorder.record_frame(delta, input.events())?;
+      state.update(delta, &input, &mut camera);
+      input.end_frame();
+    }
+    let recorded = state.session_end(&camera);
+    recorder.finish(recorded)?;
+    // The session edited the world and moved the camera
+    assert_ne!(recorded.world_digest, start.world_digest);
+    assert!(start.check(recorded).is_err());
+
+    let mut replay = InputReplay::load(&path)?;
+    std::fs::remove_file(&path)?;
+    let mut input = Input::new(ActionMap::default());
+    let (mut state, mut camera) = game();
+    let mut frames = 0;
+    while let Some(frame) = replay.next_frame() {
+      frame
+        .events
+        .into_iter()
+        .for_each(|event| input.apply(event));
+      state.update(frame.delta, &input, &mut camera);
+      input.end_frame();
+      frames += 1;
+    }
+    assert_eq!(frames, 8);
+    let end = replay.end().expect("the recording was finished");
+    let reached = state.session_end(&camera);
+    end.check(reached)?;
+    // Diverging replays are caught
+    assert!(end
+      .check(SessionEnd {
+        camera_position: reached.camera_position + Vector3::unit_x(),
+        ..reached
+      })
+      .is_err());
+    assert!(end
+      .check(SessionEnd {
+        world_digest: reached.world_digest ^ 1,
+        ..reached
+      })
+      .is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn skipped_frames_are_rejected() {
+    let text = "frame 0 0.016\nframe 2 0.016\n";
+    assert!(InputReplay::parse(text).is_err());
+  }
+
+  #[test]
+  fn reordered_frames_are_rejected() {
+    let text = "frame 1 0.016\nframe 0 0.016\n";
+    assert!(InputReplay::parse(text).is_err());
+  }
+
+  #[test]
+  fn events_before_the_first_frame_are_rejected() {
+    assert!(InputReplay::parse("press KeyW\nframe 0 0.016\n").is_err());
+  }
+}